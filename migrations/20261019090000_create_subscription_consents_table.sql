@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE subscription_consents(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    consented_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    source TEXT NOT NULL,
+    consent_text_version TEXT NOT NULL,
+    confirmed_at timestamptz NULL
+);
+CREATE INDEX subscription_consents_subscriber_id_idx
+    ON subscription_consents (subscriber_id);
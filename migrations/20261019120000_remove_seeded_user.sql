@@ -0,0 +1,6 @@
+-- Add migration script here
+-- The seed user shipped with well-known credentials. Remove it unless its
+-- password has been changed; the first owner is now created through /setup.
+DELETE FROM users
+WHERE user_id = 'ddf8994f-d522-4659-8d02-c1d479057be6'
+  AND password_hash = '$argon2id$v=19$m=15000,t=2,p=1$QioC3CqZ0+UoVopHZ9mmEQ$RmD8hl0ok+uLSDb8u6ADCLzwN1amaEx7KkO/+QlqVMs';
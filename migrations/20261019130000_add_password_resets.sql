@@ -0,0 +1,13 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+ALTER TABLE users ADD COLUMN sessions_invalidated_at timestamptz NULL;
+
+CREATE TABLE password_reset_tokens(
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    used_at timestamptz NULL
+);
+CREATE INDEX password_reset_tokens_user_id_idx ON password_reset_tokens (user_id);
@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE user_sessions(
+    session_id TEXT NOT NULL PRIMARY KEY,
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    last_seen_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
+
+-- Superseded by revoking rows in user_sessions
+ALTER TABLE users DROP COLUMN sessions_invalidated_at;
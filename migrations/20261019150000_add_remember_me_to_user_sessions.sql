@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE user_sessions ADD COLUMN remember_me BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE user_sessions ALTER COLUMN remember_me DROP DEFAULT;
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE audit_events(
+    event_id uuid NOT NULL PRIMARY KEY,
+    occurred_at timestamptz NOT NULL,
+    -- Kept when the actor is deleted so their past actions stay attributable
+    actor_id uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    actor_username TEXT NULL,
+    action TEXT NOT NULL,
+    target TEXT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL
+);
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at);
+CREATE INDEX audit_events_actor_id_idx ON audit_events (actor_id);
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN request_id TEXT NULL;
+ALTER TABLE audit_events ADD COLUMN request_id TEXT NULL;
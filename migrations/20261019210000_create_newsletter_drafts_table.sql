@@ -0,0 +1,17 @@
+-- Add migration script here
+CREATE TABLE newsletter_drafts(
+    draft_id uuid NOT NULL PRIMARY KEY,
+    title TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    -- Kept when the author is deleted so an editor can still publish it
+    author_id uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL,
+    published_at timestamptz NULL,
+    published_by uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    request_id TEXT NULL
+);
+CREATE INDEX newsletter_drafts_unpublished_idx ON newsletter_drafts (created_at)
+    WHERE published_at IS NULL;
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- Fails if two users share an address up to case, which must then be
+-- resolved by hand
+UPDATE users SET email = lower(email) WHERE email <> lower(email);
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+CREATE UNIQUE INDEX users_lower_email_idx ON users (lower(email));
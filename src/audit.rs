@@ -0,0 +1,253 @@
+use actix_web::HttpRequest;
+use actix_web::http::header::USER_AGENT;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::request_id::current_request_id;
+use crate::utils::client_ip;
+
+/// Stands in for the user agent of events recorded by the admin tool.
+pub const CLI_USER_AGENT: &str = "wizard_blog_admin";
+
+/// Something worth answering "who did this, and when?" for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    LoginSucceeded,
+    LoginFailed,
+    Logout,
+    PasswordChanged,
+    PasswordReset,
+    NewsletterPublished,
+    NewsletterDrafted,
+    UserInvited,
+    UserRoleChanged,
+    UserEmailChanged,
+    UserActivated,
+    UserDeactivated,
+    UserDeleted,
+    SessionRevoked,
+    OtherSessionsRevoked,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    SetupCompleted,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 20] = [
+        AuditAction::LoginSucceeded,
+        AuditAction::LoginFailed,
+        AuditAction::Logout,
+        AuditAction::PasswordChanged,
+        AuditAction::PasswordReset,
+        AuditAction::NewsletterPublished,
+        AuditAction::NewsletterDrafted,
+        AuditAction::UserInvited,
+        AuditAction::UserRoleChanged,
+        AuditAction::UserEmailChanged,
+        AuditAction::UserActivated,
+        AuditAction::UserDeactivated,
+        AuditAction::UserDeleted,
+        AuditAction::SessionRevoked,
+        AuditAction::OtherSessionsRevoked,
+        AuditAction::ApiTokenCreated,
+        AuditAction::ApiTokenRevoked,
+        AuditAction::TwoFactorEnabled,
+        AuditAction::TwoFactorDisabled,
+        AuditAction::SetupCompleted,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::LoginSucceeded => "login.succeeded",
+            AuditAction::LoginFailed => "login.failed",
+            AuditAction::Logout => "logout",
+            AuditAction::PasswordChanged => "password.changed",
+            AuditAction::PasswordReset => "password.reset",
+            AuditAction::NewsletterPublished => "newsletter.published",
+            AuditAction::NewsletterDrafted => "newsletter.drafted",
+            AuditAction::UserInvited => "user.invited",
+            AuditAction::UserRoleChanged => "user.role_changed",
+            AuditAction::UserEmailChanged => "user.email_changed",
+            AuditAction::UserActivated => "user.activated",
+            AuditAction::UserDeactivated => "user.deactivated",
+            AuditAction::UserDeleted => "user.deleted",
+            AuditAction::SessionRevoked => "session.revoked",
+            AuditAction::OtherSessionsRevoked => "session.revoked_others",
+            AuditAction::ApiTokenCreated => "api_token.created",
+            AuditAction::ApiTokenRevoked => "api_token.revoked",
+            AuditAction::TwoFactorEnabled => "two_factor.enabled",
+            AuditAction::TwoFactorDisabled => "two_factor.disabled",
+            AuditAction::SetupCompleted => "setup.completed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<AuditAction, String> {
+        AuditAction::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid audit action.", s))
+    }
+}
+
+/// A recorded action, as shown on the audit page and in the export.
+#[derive(serde::Serialize)]
+pub struct AuditEvent {
+    pub event_id: Uuid,
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub occurred_at: DateTime<Utc>,
+    pub actor_id: Option<Uuid>,
+    pub actor_username: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+fn serialize_rfc3339<S: serde::Serializer>(
+    instant: &DateTime<Utc>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&instant.to_rfc3339())
+}
+
+/// Narrows down the events returned by `list_audit_events`. Empty fields
+/// match everything.
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    pub actor_username: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Record that `actor_id` performed `action` on `target` through `request`.
+///
+/// Failing to write the audit trail is logged rather than returned: the action
+/// itself already happened and the user should still see its outcome.
+#[tracing::instrument(name = "Record audit event", skip(request, pool))]
+pub async fn record_audit_event(
+    actor_id: Option<Uuid>,
+    action: AuditAction,
+    target: Option<&str>,
+    request: &HttpRequest,
+    pool: &PgPool,
+) {
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+    if let Err(e) = insert_audit_event(
+        actor_id,
+        action,
+        target,
+        Some(&client_ip(request)),
+        user_agent,
+        pool,
+    )
+    .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to record an audit event."
+        );
+    }
+}
+
+/// Record `action` on `target` performed by an operator from the command
+/// line, where there is neither a logged-in actor nor a client address.
+#[tracing::instrument(name = "Record command-line audit event", skip(pool))]
+pub async fn record_cli_audit_event(action: AuditAction, target: Option<&str>, pool: &PgPool) {
+    if let Err(e) = insert_audit_event(None, action, target, None, Some(CLI_USER_AGENT), pool).await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to record an audit event."
+        );
+    }
+}
+
+async fn insert_audit_event(
+    actor_id: Option<Uuid>,
+    action: AuditAction,
+    target: Option<&str>,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let request_id = current_request_id();
+    // The username is copied so the event stays readable after the actor is
+    // renamed or deleted.
+    sqlx::query!(
+        r#"
+INSERT INTO audit_events
+    (event_id, occurred_at, actor_id, actor_username, action, target, ip_address, user_agent,
+     request_id)
+VALUES (
+    $1, $2, $3, (SELECT username FROM users WHERE user_id = $3), $4, $5, $6, $7, $8
+)
+"#,
+        Uuid::new_v4(),
+        Utc::now(),
+        actor_id,
+        action.as_str(),
+        target,
+        ip_address,
+        user_agent,
+        request_id.as_ref().map(|id| id.as_str()),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to insert an audit event.")?;
+    Ok(())
+}
+
+/// The most recent events matching `filter`, newest first. Every match is
+/// returned when `limit` is `None`.
+#[tracing::instrument(name = "List audit events", skip(pool))]
+pub async fn list_audit_events(
+    filter: &AuditFilter,
+    limit: Option<i64>,
+    pool: &PgPool,
+) -> Result<Vec<AuditEvent>, anyhow::Error> {
+    let events = sqlx::query_as!(
+        AuditEvent,
+        r#"
+SELECT event_id, occurred_at, actor_id, actor_username, action, target, ip_address, user_agent
+FROM audit_events
+WHERE ($1::text IS NULL OR action = $1)
+    AND ($2::text IS NULL OR actor_username = $2)
+    AND ($3::timestamptz IS NULL OR occurred_at >= $3)
+    AND ($4::timestamptz IS NULL OR occurred_at < $4)
+ORDER BY occurred_at DESC
+LIMIT $5
+"#,
+        filter.action.map(|action| action.as_str()),
+        filter.actor_username,
+        filter.since,
+        filter.until,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve audit events.")?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditAction;
+
+    #[test]
+    fn actions_round_trip_through_their_names() {
+        for action in AuditAction::ALL {
+            assert_eq!(AuditAction::parse(action.as_str()), Ok(action));
+        }
+        assert!(AuditAction::parse("login").is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::{Rng, distr::Alphanumeric, rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::password_reset::hash_token;
+use super::{Permission, Role};
+
+/// Marks our tokens so secret scanners can recognise leaked ones.
+const TOKEN_PREFIX: &str = "wbt_";
+
+/// An API token as shown on the token management page.
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Who an API token acts on behalf of, and what it may do.
+pub struct ApiTokenOwner {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub scopes: Vec<Permission>,
+}
+
+/// Create a token acting as `user_id`, returning the token itself. It is only
+/// stored hashed and cannot be shown again.
+#[tracing::instrument(name = "Create API token", skip(pool))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    name: &str,
+    scopes: &[Permission],
+    expires_at: Option<DateTime<Utc>>,
+    pool: &PgPool,
+) -> Result<Secret<String>, anyhow::Error> {
+    let mut rng = rng();
+    let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric) as char)
+        .take(40)
+        .collect();
+    let token = format!("{TOKEN_PREFIX}{token}");
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at, expires_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
+"#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_token(&token),
+        &scopes,
+        Utc::now(),
+        expires_at,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the API token.")?;
+    Ok(Secret::new(token))
+}
+
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_api_tokens(user_id: Uuid, pool: &PgPool) -> Result<Vec<ApiToken>, anyhow::Error> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+SELECT token_id, name, scopes, created_at, expires_at, last_used_at
+FROM api_tokens
+WHERE user_id = $1
+ORDER BY created_at DESC
+"#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve API tokens.")
+}
+
+/// Revoke one of `user_id`'s tokens, returning whether it existed.
+#[tracing::instrument(name = "Revoke API token", skip(pool))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    token_id: Uuid,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM api_tokens WHERE user_id = $1 AND token_id = $2",
+        user_id,
+        token_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke the API token.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Look up the active user behind an unexpired `token`, recording its use.
+#[tracing::instrument(name = "Authenticate API token", skip(token, pool))]
+pub async fn authenticate_api_token(
+    token: &Secret<String>,
+    pool: &PgPool,
+) -> Result<Option<ApiTokenOwner>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+WITH used AS (
+    UPDATE api_tokens
+    SET last_used_at = now()
+    WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > now())
+    RETURNING user_id, scopes
+)
+SELECT users.user_id, users.role, used.scopes
+FROM users
+JOIN used ON used.user_id = users.user_id
+WHERE users.is_active
+"#,
+        hash_token(token.expose_secret()),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to check the API token.")?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let role = Role::parse(&row.role).map_err(anyhow::Error::msg)?;
+    // Scopes that are no longer known grant nothing
+    let scopes = row
+        .scopes
+        .iter()
+        .filter_map(|scope| Permission::parse(scope).ok())
+        .collect();
+    Ok(Some(ApiTokenOwner {
+        user_id: row.user_id,
+        role,
+        scopes,
+    }))
+}
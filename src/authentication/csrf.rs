@@ -0,0 +1,62 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{FromRequest, HttpMessage, web};
+
+use super::ApiTokenScopes;
+use crate::error::AppError;
+use crate::session_state::TypedSession;
+use crate::utils::{constant_time_eq, e500};
+
+/// Header accepted as an alternative to the `csrf_token` form field.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+#[derive(serde::Deserialize)]
+struct CsrfForm {
+    csrf_token: Option<String>,
+}
+
+/// Reject state-changing requests whose CSRF token does not match the one
+/// stored in the session by the form that was rendered to the user. Must be
+/// layered inside `reject_anonymous_users` for requests made with API tokens
+/// to be let through.
+pub async fn reject_invalid_csrf_tokens(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    // API tokens are never sent by browsers on their own
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        || req.extensions().get::<ApiTokenScopes>().is_some()
+    {
+        return next.call(req).await;
+    }
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let expected = session.get_csrf_token().map_err(e500)?;
+
+    let submitted = match req.headers().get(CSRF_HEADER).and_then(|h| h.to_str().ok()) {
+        Some(token) => Some(token.to_owned()),
+        None => {
+            let (http_request, payload) = req.parts_mut();
+            let body = web::Bytes::from_request(http_request, payload).await?;
+            let form =
+                web::Form::<CsrfForm>::from_request(http_request, &mut Payload::from(body.clone()))
+                    .await;
+            req.set_payload(Payload::from(body));
+            form.ok().and_then(|f| f.into_inner().csrf_token)
+        }
+    };
+
+    match (expected, submitted) {
+        (Some(expected), Some(submitted)) if constant_time_eq(&expected, &submitted) => {
+            next.call(req).await
+        }
+        _ => {
+            tracing::warn!("Rejecting a request with a missing or invalid CSRF token.");
+            Err(AppError::Forbidden("Invalid CSRF token.".into()).into())
+        }
+    }
+}
@@ -1,12 +1,20 @@
 use std::ops::Deref;
 
 use actix_web::body::MessageBody;
+use actix_web::cookie::{Cookie, SameSite, time::Duration as CookieDuration};
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
+use actix_web::http::header::{AUTHORIZATION, HeaderValue, SET_COOKIE, WWW_AUTHENTICATE};
 use actix_web::middleware::Next;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::{FromRequest, HttpMessage, HttpResponse, web};
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
 use uuid::Uuid;
 
+use super::{ApiTokenScopes, Role, SessionCutoffs, authenticate_api_token};
+use crate::configuration::SessionSettings;
+use crate::error::AppError;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 
@@ -25,23 +33,263 @@ impl Deref for UserId {
     }
 }
 
+/// Reject requests without a logged-in, active user, or a valid API token in
+/// an `Authorization: Bearer` header. The user's id and role are made
+/// available to inner services as request extensions.
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
 ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if let Some(token) = bearer_token(&req) {
+        authenticate_with_api_token(&mut req, token).await?;
+        return next.call(req).await;
+    }
     let session = {
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
-            req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
+    if authenticate_with_session(&mut req, &session).await? {
+        next.call(req).await
+    } else {
+        // Deleted or deactivated users and revoked sessions are logged out
+        // immediately
+        session.log_out();
+        let response = see_other("/login");
+        let e = anyhow::anyhow!("The user has not logged in");
+        Err(InternalError::from_response(e, response).into())
+    }
+}
+
+/// Like `reject_anonymous_users`, but answers anonymous requests with a
+/// `401 Unauthorized` instead of sending them to the login page.
+pub async fn reject_unauthenticated_api_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if let Some(token) = bearer_token(&req) {
+        authenticate_with_api_token(&mut req, token).await?;
+        return next.call(req).await;
+    }
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    if authenticate_with_session(&mut req, &session).await? {
+        next.call(req).await
+    } else {
+        Err(unauthorized(anyhow::anyhow!(
+            "The request is not authenticated"
+        )))
+    }
+}
+
+/// Reject requests authenticated with an API token, for pages managing the
+/// account itself. Must be layered inside `reject_anonymous_users`.
+pub async fn reject_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.extensions().get::<ApiTokenScopes>().is_some() {
+        return Err(
+            AppError::Forbidden("API tokens cannot be used for this action.".into()).into(),
+        );
+    }
+    next.call(req).await
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<Secret<String>> {
+    let header = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = header.strip_prefix("Bearer ")?.trim();
+    Some(Secret::new(token.to_owned()))
+}
+
+fn unauthorized(e: anyhow::Error) -> actix_web::Error {
+    let response = HttpResponse::Unauthorized()
+        .insert_header((WWW_AUTHENTICATE, "Bearer"))
+        .finish();
+    InternalError::from_response(e, response).into()
+}
+
+async fn authenticate_with_api_token(
+    req: &mut ServiceRequest,
+    token: Secret<String>,
+) -> Result<(), actix_web::Error> {
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500(anyhow::anyhow!("The database pool is not configured")))?;
+    let Some(owner) = authenticate_api_token(&token, pool).await.map_err(e500)? else {
+        tracing::warn!("Rejecting a request with an invalid or expired API token.");
+        return Err(unauthorized(anyhow::anyhow!("Invalid API token")));
+    };
+    req.extensions_mut().insert(UserId(owner.user_id));
+    req.extensions_mut().insert(owner.role);
+    req.extensions_mut().insert(ApiTokenScopes(owner.scopes));
+    Ok(())
+}
+
+/// Whether the session belongs to an active user, loading their id and role
+/// into the request extensions if so.
+async fn authenticate_with_session(
+    req: &mut ServiceRequest,
+    session: &TypedSession,
+) -> Result<bool, actix_web::Error> {
+    let Some(user_id) = session.get_user_id().map_err(e500)? else {
+        return Ok(false);
+    };
+    let Some(session_id) = session.get_session_id().map_err(e500)? else {
+        return Ok(false);
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500(anyhow::anyhow!("The database pool is not configured")))?;
+    let settings = req
+        .app_data::<web::Data<SessionSettings>>()
+        .ok_or_else(|| e500(anyhow::anyhow!("The session settings are not configured")))?;
+    let Some((role, remember_me)) = get_active_user_role(user_id, &session_id, settings, pool)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(false);
+    };
+    req.extensions_mut().insert(UserId(user_id));
+    req.extensions_mut().insert(role);
+    if remember_me {
+        req.extensions_mut().insert(RememberedSession);
+    }
+    Ok(true)
+}
+
+/// The user's role and whether they asked to be remembered, or `None` if the
+/// user is gone, deactivated, or the session has been revoked or has expired.
+/// Marks the session as seen now.
+#[tracing::instrument(name = "Get active user role", skip(session_id, settings, pool))]
+async fn get_active_user_role(
+    user_id: Uuid,
+    session_id: &str,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<Option<(Role, bool)>, anyhow::Error> {
+    let cutoffs = SessionCutoffs::now(settings);
+    let row = sqlx::query!(
+        r#"
+WITH seen AS (
+    UPDATE user_sessions
+    SET last_seen_at = now()
+    WHERE session_id = $2 AND user_id = $1
+        AND created_at > $3
+        AND CASE WHEN $4::boolean THEN last_seen_at ELSE created_at END
+            > CASE WHEN remember_me THEN $5::timestamptz ELSE $6::timestamptz END
+    RETURNING user_id, remember_me
+)
+SELECT users.role, seen.remember_me
+FROM users
+JOIN seen ON seen.user_id = users.user_id
+WHERE users.is_active
+"#,
+        user_id,
+        session_id,
+        cutoffs.created_after,
+        cutoffs.rolling,
+        cutoffs.remembered_idle_after,
+        cutoffs.idle_after,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the user's role.")?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let role = Role::parse(&row.role).map_err(anyhow::Error::msg)?;
+    Ok(Some((role, row.remember_me)))
+}
+
+/// Marks requests made from a session started with "Remember me" ticked.
+#[derive(Copy, Clone, Debug)]
+pub struct RememberedSession;
+
+/// Turn the session cookie of remembered sessions into a persistent one that
+/// survives browser restarts. With rolling sessions its expiry is pushed back
+/// on every request. Must wrap the session middleware.
+pub async fn persist_remembered_sessions(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let settings = req.app_data::<web::Data<SessionSettings>>().cloned();
+    let mut res = next.call(req).await?;
+    let Some(settings) = settings else {
+        return Ok(res);
+    };
+    if res
+        .request()
+        .extensions()
+        .get::<RememberedSession>()
+        .is_none()
+    {
+        return Ok(res);
+    }
+    let max_age = CookieDuration::seconds(
+        i64::try_from(settings.remember_me_ttl_seconds).unwrap_or(i64::MAX),
+    );
+
+    let mut set_cookies: Vec<HeaderValue> = res.headers().get_all(SET_COOKIE).cloned().collect();
+    let mut session_cookie_set = false;
+    for value in set_cookies.iter_mut() {
+        let Some(mut cookie) = value
+            .to_str()
+            .ok()
+            .and_then(|value| Cookie::parse(value.to_owned()).ok())
+        else {
+            continue;
+        };
+        if cookie.name() != settings.cookie_name {
+            continue;
         }
-        None => {
-            let response = see_other("/login");
-            let e = anyhow::anyhow!("The user has not logged in");
-            Err(InternalError::from_response(e, response).into())
+        session_cookie_set = true;
+        // Leave the removal cookie of a logout alone
+        if cookie.max_age().is_none() {
+            cookie.set_max_age(max_age);
+            *value = HeaderValue::from_str(&cookie.to_string()).map_err(e500)?;
         }
     }
+    if !session_cookie_set
+        && settings.rolling
+        && let Some(cookie) = res.request().cookie(&settings.cookie_name)
+    {
+        let mut cookie = Cookie::new(cookie.name().to_owned(), cookie.value().to_owned());
+        cookie.set_path("/");
+        cookie.set_http_only(true);
+        cookie.set_secure(settings.cookie_secure);
+        cookie.set_same_site(SameSite::from(settings.cookie_same_site));
+        if let Some(domain) = &settings.cookie_domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie.set_max_age(max_age);
+        let cookie = cookie.encoded().to_string();
+        set_cookies.push(HeaderValue::from_str(&cookie).map_err(e500)?);
+    }
+    let headers = res.headers_mut();
+    headers.remove(SET_COOKIE);
+    for value in set_cookies {
+        headers.append(SET_COOKIE, value);
+    }
+    Ok(res)
+}
+
+/// Keep admins who must enroll in two-factor authentication on the enrollment
+/// page until they have done so.
+pub async fn require_two_factor_enrollment(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let exempt = matches!(req.path(), "/admin/two-factor" | "/admin/logout");
+    if exempt || !session.two_factor_enrollment_required().map_err(e500)? {
+        return next.call(req).await;
+    }
+    let response = see_other("/admin/two-factor");
+    let e = anyhow::anyhow!("The user must enroll in two-factor authentication");
+    Err(InternalError::from_response(e, response).into())
 }
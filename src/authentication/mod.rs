@@ -1,4 +1,22 @@
+mod api_tokens;
+mod csrf;
 mod middleware;
+mod oidc;
 mod password;
-pub use middleware::{UserId, reject_anonymous_users};
+mod password_policy;
+mod password_reset;
+mod roles;
+mod sessions;
+pub mod two_factor;
+pub use api_tokens::*;
+pub use csrf::{CSRF_HEADER, reject_invalid_csrf_tokens};
+pub use middleware::{
+    RememberedSession, UserId, persist_remembered_sessions, reject_anonymous_users,
+    reject_api_tokens, reject_unauthenticated_api_requests, require_two_factor_enrollment,
+};
+pub use oidc::find_user_for_identity;
 pub use password::*;
+pub use password_policy::{PasswordPolicyViolation, check_password_policy};
+pub use password_reset::*;
+pub use roles::{ApiTokenScopes, Permission, Role, require_permission};
+pub use sessions::*;
@@ -0,0 +1,71 @@
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::oidc_client::IdTokenClaims;
+
+/// The active user an OIDC identity logs in as. An identity seen for the first
+/// time is linked to the user with the same email address, if the provider
+/// verified it and `link_by_email` allows it. Users are never created here:
+/// they must have been invited first.
+#[tracing::instrument(name = "Find user for OIDC identity", skip(claims, pool), fields(subject = %claims.sub))]
+pub async fn find_user_for_identity(
+    issuer: &str,
+    claims: &IdTokenClaims,
+    link_by_email: bool,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let linked = sqlx::query!(
+        r#"
+SELECT users.user_id, users.is_active
+FROM oidc_identities
+JOIN users ON users.user_id = oidc_identities.user_id
+WHERE oidc_identities.issuer = $1 AND oidc_identities.subject = $2
+"#,
+        issuer,
+        claims.sub,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the OIDC identity.")?;
+    if let Some(linked) = linked {
+        return Ok(linked.is_active.then_some(linked.user_id));
+    }
+
+    let Some(email) = claims.email.as_deref().filter(|_| claims.email_verified) else {
+        return Ok(None);
+    };
+    if !link_by_email {
+        return Ok(None);
+    }
+    let user_id = sqlx::query_scalar!(
+        "SELECT user_id FROM users WHERE lower(email) = lower($1) AND is_active",
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the user by email.")?;
+    let Some(user_id) = user_id else {
+        return Ok(None);
+    };
+    // A user is linked to at most one identity per provider
+    let inserted = sqlx::query!(
+        r#"
+INSERT INTO oidc_identities (issuer, subject, user_id, created_at)
+SELECT $1, $2, $3, $4
+WHERE NOT EXISTS (
+    SELECT 1 FROM oidc_identities WHERE issuer = $1 AND user_id = $3
+)
+ON CONFLICT DO NOTHING
+"#,
+        issuer,
+        claims.sub,
+        user_id,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to link the OIDC identity to the user.")?;
+    Ok((inserted.rows_affected() == 1).then_some(user_id))
+}
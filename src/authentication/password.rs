@@ -1,3 +1,7 @@
+use crate::authentication::Role;
+use crate::background::BackgroundTasks;
+use crate::configuration::PasswordHashingSettings;
+use crate::domain::SubscriberEmail;
 use crate::telemetry::spawn_blocking_with_tracing;
 use anyhow::Context;
 use argon2::{
@@ -5,7 +9,8 @@ use argon2::{
     password_hash::{SaltString, rand_core::OsRng},
 };
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::Instrument;
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -20,37 +25,60 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+#[tracing::instrument(
+    name = "Validate credentials",
+    skip(credentials, hashing, pool, background_tasks)
+)]
 pub async fn validate_credentials(
     credentials: Credentials,
+    hashing: &PasswordHashingSettings,
     pool: &PgPool,
+    background_tasks: &BackgroundTasks,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
+    // Unknown usernames are checked against a dummy hash with the current
+    // parameters, so they take as long to reject as wrong passwords.
+    let mut expected_password_hash = Secret::new(format!(
+        "$argon2id$v=19$m={},t={},p={}$\
     gZiV/M1gPc22ElAH/Jh1Hw$\
-    CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+    CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno",
+        hashing.memory_cost_kib, hashing.iterations, hashing.parallelism
+    ));
 
     if let Some((stored_user_id, stored_expected_password_hash)) =
-        get_stored_credentials(&credentials.username, &pool).await?
+        get_stored_credentials(&credentials.username, pool).await?
     {
         user_id = Some(stored_user_id);
         expected_password_hash = stored_expected_password_hash;
     }
 
-    spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+    let password = credentials.password.clone();
+    let stored_hash = expected_password_hash.clone();
+    let params = hashing.params()?;
+    let needs_rehash = spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, password, &params)
     })
     .await
     .context("Failed to spawn blocking task.")??;
 
-    user_id
+    let user_id = user_id
         .ok_or_else(|| anyhow::anyhow!("Unknown username."))
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+    if needs_rehash {
+        spawn_rehash(
+            user_id,
+            stored_hash,
+            credentials.password,
+            hashing.clone(),
+            pool.clone(),
+            background_tasks,
+        );
+    }
+    Ok(user_id)
 }
 
+/// Check `password_candidate`, returning whether the stored hash was created
+/// with parameters other than the configured ones.
 #[tracing::instrument(
     name = "Verify password hash",
     skip(expected_password_hash, password_candidate)
@@ -58,7 +86,8 @@ pub async fn validate_credentials(
 fn verify_password_hash(
     expected_password_hash: Secret<String>,
     password_candidate: Secret<String>,
-) -> Result<(), AuthError> {
+    params: &Params,
+) -> Result<bool, AuthError> {
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
     Argon2::default()
@@ -67,7 +96,72 @@ fn verify_password_hash(
             &expected_password_hash,
         )
         .context("Invalid password.")
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+
+    let outdated = expected_password_hash.algorithm != Algorithm::Argon2id.ident()
+        || expected_password_hash.version != Some(Version::V0x13.into())
+        || Params::try_from(&expected_password_hash).map_or(true, |stored| {
+            stored.m_cost() != params.m_cost()
+                || stored.t_cost() != params.t_cost()
+                || stored.p_cost() != params.p_cost()
+        });
+    Ok(outdated)
+}
+
+/// Upgrade a hash created with outdated parameters, now that the plaintext
+/// password is known, without delaying the login.
+fn spawn_rehash(
+    user_id: uuid::Uuid,
+    old_password_hash: Secret<String>,
+    password: Secret<String>,
+    hashing: PasswordHashingSettings,
+    pool: PgPool,
+    background_tasks: &BackgroundTasks,
+) {
+    let span = tracing::info_span!("Upgrade password hash", %user_id);
+    background_tasks.spawn(
+        async move {
+            if let Err(e) =
+                rehash_password(user_id, old_password_hash, password, &hashing, &pool).await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to upgrade the password hash."
+                );
+            }
+        }
+        .instrument(span),
+    );
+}
+
+async fn rehash_password(
+    user_id: uuid::Uuid,
+    old_password_hash: Secret<String>,
+    password: Secret<String>,
+    hashing: &PasswordHashingSettings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let params = hashing.params()?;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await?
+            .context("Failed to hash password")?;
+    // Leave the hash alone if the password changed in the meantime
+    sqlx::query!(
+        r#"
+UPDATE users
+SET password_hash = $1
+WHERE user_id = $2 AND password_hash = $3
+"#,
+        password_hash.expose_secret(),
+        user_id,
+        old_password_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the upgraded password hash.")?;
+    Ok(())
 }
 
 #[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
@@ -79,7 +173,7 @@ async fn get_stored_credentials(
         r#"
 SELECT user_id, password_hash
 FROM users
-WHERE username = $1
+WHERE username = $1 AND is_active
 "#,
         username,
     )
@@ -90,15 +184,18 @@ WHERE username = $1
     Ok(row)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+#[tracing::instrument(name = "Change password", skip(password, hashing, executor))]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
-    pool: &PgPool,
+    hashing: &PasswordHashingSettings,
+    executor: impl sqlx::PgExecutor<'_>,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+    let params = hashing.params()?;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await?
+            .context("Failed to hash password")?;
     sqlx::query!(
         r#"
 UPDATE users
@@ -108,20 +205,120 @@ WHERE user_id = $2
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(executor)
     .await
     .context("Failed to change user's password in the database.")?;
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
-    let salt = SaltString::generate(&mut OsRng);
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
+#[tracing::instrument(name = "Create user", skip(password, hashing, pool))]
+pub async fn create_user(
+    username: &str,
+    email: Option<&SubscriberEmail>,
+    password: Secret<String>,
+    role: Role,
+    hashing: &PasswordHashingSettings,
+    pool: &PgPool,
+) -> Result<uuid::Uuid, anyhow::Error> {
+    let params = hashing.params()?;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await?
+            .context("Failed to hash password")?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let user_id = insert_user(&mut transaction, username, email, password_hash, role).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new user.")?;
+    Ok(user_id)
+}
+
+/// Create the first owner account, returning `None` if any user already exists.
+#[tracing::instrument(name = "Create first owner", skip(password, hashing, pool))]
+pub async fn create_first_owner(
+    username: &str,
+    email: Option<&SubscriberEmail>,
+    password: Secret<String>,
+    hashing: &PasswordHashingSettings,
+    pool: &PgPool,
+) -> Result<Option<uuid::Uuid>, anyhow::Error> {
+    let params = hashing.params()?;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await?
+            .context("Failed to hash password")?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    // Serialise concurrent bootstrap attempts so only one of them can win
+    sqlx::query!("LOCK TABLE users IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to lock the users table.")?;
+    if has_users(&mut *transaction).await? {
+        return Ok(None);
+    }
+    let user_id = insert_user(
+        &mut transaction,
+        username,
+        email,
+        password_hash,
+        Role::Owner,
     )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the first owner.")?;
+    Ok(Some(user_id))
+}
+
+#[tracing::instrument(name = "Check if any user exists", skip(executor))]
+pub async fn has_users(executor: impl sqlx::PgExecutor<'_>) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT EXISTS(SELECT 1 FROM users) AS "exists!""#)
+        .fetch_one(executor)
+        .await
+        .context("Failed to perform a query to check for existing users.")?;
+    Ok(row.exists)
+}
+
+async fn insert_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: Option<&SubscriberEmail>,
+    password_hash: Secret<String>,
+    role: Role,
+) -> Result<uuid::Uuid, anyhow::Error> {
+    let user_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+INSERT INTO users (user_id, username, email, password_hash, role)
+VALUES ($1, $2, lower($3), $4, $5)
+"#,
+        user_id,
+        username,
+        email.map(|email| email.as_ref()),
+        password_hash.expose_secret(),
+        role.as_str(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to insert a new user in the database.")?;
+    Ok(user_id)
+}
+
+fn compute_password_hash(
+    password: Secret<String>,
+    params: Params,
+) -> Result<Secret<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(Secret::new(password_hash))
 }
@@ -0,0 +1,206 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sha1::{Digest, Sha1};
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::configuration::PasswordPolicySettings;
+use crate::telemetry::spawn_blocking_with_tracing;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordPolicyViolation {
+    TooShort(usize),
+    TooLong(usize),
+    SameAsUsername,
+    SameAsCurrentPassword,
+    TooWeak,
+    Breached,
+}
+
+impl std::fmt::Display for PasswordPolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort(min) => {
+                write!(f, "The password must be at least {min} characters long.")
+            }
+            Self::TooLong(max) => write!(f, "The password must be at most {max} characters long."),
+            Self::SameAsUsername => write!(f, "The password must not be the same as the username."),
+            Self::SameAsCurrentPassword => {
+                write!(
+                    f,
+                    "The new password must be different from the current one."
+                )
+            }
+            Self::TooWeak => write!(
+                f,
+                "The password is too easy to guess - try a longer passphrase or fewer common words."
+            ),
+            Self::Breached => write!(
+                f,
+                "The password has appeared in a data breach - please choose a different one."
+            ),
+        }
+    }
+}
+
+/// Check `password` against the configured policy, returning every rule it
+/// breaks. `current_password` is only known when a logged-in user changes it.
+#[tracing::instrument(
+    name = "Check password policy",
+    skip(settings, password, current_password)
+)]
+pub async fn check_password_policy(
+    settings: &PasswordPolicySettings,
+    password: &Secret<String>,
+    username: Option<&str>,
+    current_password: Option<&Secret<String>>,
+) -> Result<Vec<PasswordPolicyViolation>, anyhow::Error> {
+    let mut violations = check_password_rules(settings, password, username, current_password);
+    if let Some(directory) = &settings.breached_passwords_directory
+        && !violations.contains(&PasswordPolicyViolation::TooLong(settings.max_length))
+    {
+        let directory = directory.clone();
+        let password = password.clone();
+        let breached =
+            spawn_blocking_with_tracing(move || is_breached(&directory, password.expose_secret()))
+                .await
+                .context("Failed to spawn blocking task.")??;
+        if breached {
+            violations.push(PasswordPolicyViolation::Breached);
+        }
+    }
+    Ok(violations)
+}
+
+fn check_password_rules(
+    settings: &PasswordPolicySettings,
+    password: &Secret<String>,
+    username: Option<&str>,
+    current_password: Option<&Secret<String>>,
+) -> Vec<PasswordPolicyViolation> {
+    let password = password.expose_secret();
+    let mut violations = Vec::new();
+    let length = password.graphemes(true).count();
+    if length < settings.min_length {
+        violations.push(PasswordPolicyViolation::TooShort(settings.min_length));
+    }
+    if length > settings.max_length {
+        // Estimating the strength of arbitrarily long input is expensive
+        violations.push(PasswordPolicyViolation::TooLong(settings.max_length));
+        return violations;
+    }
+    if username.is_some_and(|username| username.eq_ignore_ascii_case(password)) {
+        violations.push(PasswordPolicyViolation::SameAsUsername);
+    }
+    if current_password.is_some_and(|current| current.expose_secret() == password) {
+        violations.push(PasswordPolicyViolation::SameAsCurrentPassword);
+    }
+    let user_inputs: Vec<&str> = username.into_iter().collect();
+    let score: u8 = zxcvbn::zxcvbn(password, &user_inputs).score().into();
+    if score < settings.min_strength {
+        violations.push(PasswordPolicyViolation::TooWeak);
+    }
+    violations
+}
+
+/// Look `password` up in a local copy of the Pwned Passwords range files.
+/// Only the file for the first five characters of its SHA-1 hash is read.
+fn is_breached(directory: &Path, password: &str) -> Result<bool, anyhow::Error> {
+    let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(5);
+    let range = match std::fs::read_to_string(directory.join(prefix)) {
+        Ok(range) => range,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            tracing::warn!(prefix, "The breached password range file is missing.");
+            return Ok(false);
+        }
+        Err(e) => return Err(e).context("Failed to read a breached password range file."),
+    };
+    Ok(range.lines().any(|line| {
+        let mut parts = line.trim().split(':');
+        let line_suffix = parts.next().unwrap_or_default();
+        // Padding entries added to hide the range size have a count of 0
+        let count: u64 = parts.next().and_then(|c| c.parse().ok()).unwrap_or(1);
+        count > 0 && line_suffix.eq_ignore_ascii_case(suffix)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PasswordPolicyViolation, check_password_rules, is_breached};
+    use crate::configuration::PasswordPolicySettings;
+    use secrecy::Secret;
+
+    fn settings() -> PasswordPolicySettings {
+        PasswordPolicySettings {
+            min_length: 12,
+            max_length: 128,
+            min_strength: 3,
+            breached_passwords_directory: None,
+        }
+    }
+
+    fn check(
+        password: &str,
+        username: Option<&str>,
+        current: Option<&str>,
+    ) -> Vec<PasswordPolicyViolation> {
+        let current = current.map(|c| Secret::new(c.to_string()));
+        check_password_rules(
+            &settings(),
+            &Secret::new(password.to_string()),
+            username,
+            current.as_ref(),
+        )
+    }
+
+    #[test]
+    fn a_long_random_password_is_accepted() {
+        assert!(check("correct-horse-battery-staple-91", Some("admin"), None).is_empty());
+    }
+
+    #[test]
+    fn length_is_counted_in_graphemes() {
+        let violations = check(&"ё".repeat(11), None, None);
+        assert!(violations.contains(&PasswordPolicyViolation::TooShort(12)));
+        let violations = check(&"ё".repeat(129), None, None);
+        assert_eq!(violations, vec![PasswordPolicyViolation::TooLong(128)]);
+    }
+
+    #[test]
+    fn the_username_is_rejected() {
+        let violations = check("wizard-of-the-blog", Some("Wizard-Of-The-Blog"), None);
+        assert!(violations.contains(&PasswordPolicyViolation::SameAsUsername));
+    }
+
+    #[test]
+    fn the_current_password_is_rejected() {
+        let password = "a-perfectly-fine-passphrase";
+        let violations = check(password, None, Some(password));
+        assert_eq!(
+            violations,
+            vec![PasswordPolicyViolation::SameAsCurrentPassword]
+        );
+    }
+
+    #[test]
+    fn guessable_passwords_are_rejected() {
+        let violations = check("password1234", None, None);
+        assert_eq!(violations, vec![PasswordPolicyViolation::TooWeak]);
+    }
+
+    #[test]
+    fn breached_passwords_are_found_in_their_range_file() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&directory).unwrap();
+        // SHA-1("password") = 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+        std::fs::write(
+            directory.join("5BAA6"),
+            "003D68EB55068C33ACE09247EE4C639306B:3\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:9659365\r\n",
+        )
+        .unwrap();
+        assert!(is_breached(&directory, "password").unwrap());
+        assert!(!is_breached(&directory, "a-password-nobody-has-used").unwrap());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::Context;
+use chrono::Utc;
+use rand::{Rng, distr::Alphanumeric, rng};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Reset and API tokens are only ever stored hashed, so a database leak
+/// cannot be turned into account takeovers.
+pub(super) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Create a reset token for `user_id` valid for `ttl`, returning the token to
+/// put in the emailed link.
+#[tracing::instrument(name = "Create password reset token", skip(pool))]
+pub async fn create_password_reset_token(
+    user_id: Uuid,
+    ttl: Duration,
+    pool: &PgPool,
+) -> Result<String, anyhow::Error> {
+    let mut rng = rng();
+    let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric) as char)
+        .take(32)
+        .collect();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+VALUES ($1, $2, $3, $4)
+"#,
+        hash_token(&token),
+        user_id,
+        now,
+        now + ttl,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the password reset token.")?;
+    Ok(token)
+}
+
+/// The user `token` belongs to, if it could still be used to reset a password.
+#[tracing::instrument(name = "Check password reset token", skip(token, pool))]
+pub async fn get_password_reset_token_user(
+    token: &str,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+SELECT user_id
+FROM password_reset_tokens
+WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+"#,
+        hash_token(token),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to check the password reset token.")?;
+    Ok(row.map(|row| row.user_id))
+}
+
+/// Mark `token` as used, returning the user it belongs to if it was valid.
+/// Every other outstanding token for that user is retired as well. Nothing
+/// sticks unless `transaction` is committed.
+#[tracing::instrument(name = "Consume password reset token", skip(token, transaction))]
+pub async fn consume_password_reset_token(
+    token: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+UPDATE password_reset_tokens
+SET used_at = now()
+WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+RETURNING user_id
+"#,
+        hash_token(token),
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to consume the password reset token.")?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    sqlx::query!(
+        r#"
+UPDATE password_reset_tokens
+SET used_at = now()
+WHERE user_id = $1 AND used_at IS NULL
+"#,
+        row.user_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to retire the other password reset tokens.")?;
+    Ok(Some(row.user_id))
+}
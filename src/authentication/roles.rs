@@ -0,0 +1,152 @@
+use actix_web::HttpMessage;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+
+use crate::error::AppError;
+
+/// What a user is allowed to do in the admin area. Each role can do
+/// everything the roles below it can.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Author,
+    Editor,
+    Owner,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Permission {
+    DraftNewsletters,
+    PublishNewsletters,
+    ViewSubscribers,
+    ManageUsers,
+    ViewAuditLog,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Owner, Role::Editor, Role::Author, Role::Viewer];
+
+    pub fn parse(s: &str) -> Result<Role, String> {
+        match s {
+            "owner" => Ok(Role::Owner),
+            "editor" => Ok(Role::Editor),
+            "author" => Ok(Role::Author),
+            "viewer" => Ok(Role::Viewer),
+            other => Err(format!("{} is not a valid role.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Author => "author",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        let minimum = match permission {
+            Permission::DraftNewsletters => Role::Author,
+            Permission::PublishNewsletters | Permission::ViewSubscribers => Role::Editor,
+            Permission::ManageUsers | Permission::ViewAuditLog => Role::Owner,
+        };
+        *self >= minimum
+    }
+}
+
+impl Permission {
+    pub const ALL: [Permission; 5] = [
+        Permission::DraftNewsletters,
+        Permission::PublishNewsletters,
+        Permission::ViewSubscribers,
+        Permission::ManageUsers,
+        Permission::ViewAuditLog,
+    ];
+
+    /// The name of the API token scope granting this permission.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::DraftNewsletters => "newsletters:draft",
+            Permission::PublishNewsletters => "newsletters:publish",
+            Permission::ViewSubscribers => "subscribers:read",
+            Permission::ManageUsers => "users:manage",
+            Permission::ViewAuditLog => "audit:read",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Permission, String> {
+        Permission::ALL
+            .into_iter()
+            .find(|permission| permission.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid scope.", s))
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The scopes of the API token a request was authenticated with. Absent for
+/// requests authenticated with a session.
+#[derive(Clone, Debug)]
+pub struct ApiTokenScopes(pub Vec<Permission>);
+
+/// Reject requests from users whose role does not grant `permission`, or
+/// made with an API token lacking the matching scope. Must be layered inside
+/// `reject_anonymous_users`, which loads the role.
+pub async fn require_permission(
+    permission: Permission,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let role = req.extensions().get::<Role>().copied();
+    let in_scope = req
+        .extensions()
+        .get::<ApiTokenScopes>()
+        .is_none_or(|scopes| scopes.0.contains(&permission));
+    match role {
+        Some(role) if role.can(permission) && in_scope => next.call(req).await,
+        _ => Err(
+            AppError::Forbidden("You do not have permission to perform this action.".into()).into(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Role};
+
+    #[test]
+    fn roles_round_trip_through_their_database_representation() {
+        for role in Role::ALL {
+            assert_eq!(Role::parse(role.as_str()), Ok(role));
+        }
+        assert!(Role::parse("admin").is_err());
+    }
+
+    #[test]
+    fn authors_can_draft_but_not_publish_newsletters() {
+        assert!(Role::Author.can(Permission::DraftNewsletters));
+        assert!(!Role::Author.can(Permission::PublishNewsletters));
+        assert!(Role::Editor.can(Permission::PublishNewsletters));
+        assert!(!Role::Viewer.can(Permission::DraftNewsletters));
+    }
+
+    #[test]
+    fn scopes_round_trip_through_their_names() {
+        for permission in Permission::ALL {
+            assert_eq!(Permission::parse(permission.as_str()), Ok(permission));
+        }
+        assert!(Permission::parse("newsletters:*").is_err());
+    }
+
+    #[test]
+    fn only_owners_can_manage_users() {
+        assert!(Role::Owner.can(Permission::ManageUsers));
+        assert!(!Role::Editor.can(Permission::ManageUsers));
+    }
+}
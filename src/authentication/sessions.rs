@@ -0,0 +1,174 @@
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::{Rng, distr::Alphanumeric, rng};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::configuration::SessionSettings;
+
+/// An admin session as shown on the session management page.
+pub struct ActiveSession {
+    pub session_id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// The instants sessions must be newer than to still be valid, as of now.
+pub(crate) struct SessionCutoffs {
+    pub created_after: DateTime<Utc>,
+    pub idle_after: DateTime<Utc>,
+    pub remembered_idle_after: DateTime<Utc>,
+    /// Whether the idle timeout counts from the last request or from login.
+    pub rolling: bool,
+}
+
+impl SessionCutoffs {
+    pub(crate) fn now(settings: &SessionSettings) -> Self {
+        Self {
+            created_after: ago(settings.absolute_lifetime()),
+            idle_after: ago(settings.idle_timeout()),
+            remembered_idle_after: ago(settings.remember_me_ttl()),
+            rolling: settings.rolling,
+        }
+    }
+}
+
+fn ago(duration: Duration) -> DateTime<Utc> {
+    TimeDelta::from_std(duration)
+        .ok()
+        .and_then(|delta| Utc::now().checked_sub_signed(delta))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+/// Record a new logged-in session for `user_id`, returning the id to keep in
+/// the session state so the session can be listed and revoked.
+#[tracing::instrument(name = "Register session", skip(settings, pool))]
+pub async fn register_session(
+    user_id: Uuid,
+    ip_address: &str,
+    user_agent: Option<&str>,
+    remember_me: bool,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<String, anyhow::Error> {
+    let mut rng = rng();
+    let session_id: String = std::iter::repeat_with(|| rng.sample(Alphanumeric) as char)
+        .take(32)
+        .collect();
+    let now = Utc::now();
+    let cutoffs = SessionCutoffs::now(settings);
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+DELETE FROM user_sessions
+WHERE user_id = $1 AND NOT (
+    created_at > $2
+    AND CASE WHEN $3::boolean THEN last_seen_at ELSE created_at END
+        > CASE WHEN remember_me THEN $4::timestamptz ELSE $5::timestamptz END
+)
+"#,
+        user_id,
+        cutoffs.created_after,
+        cutoffs.rolling,
+        cutoffs.remembered_idle_after,
+        cutoffs.idle_after,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete expired sessions.")?;
+    sqlx::query!(
+        r#"
+INSERT INTO user_sessions
+    (session_id, user_id, created_at, last_seen_at, ip_address, user_agent, remember_me)
+VALUES ($1, $2, $3, $3, $4, $5, $6)
+"#,
+        session_id,
+        user_id,
+        now,
+        ip_address,
+        user_agent,
+        remember_me,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the session.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new session.")?;
+    Ok(session_id)
+}
+
+#[tracing::instrument(name = "List sessions", skip(settings, pool))]
+pub async fn list_sessions(
+    user_id: Uuid,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<Vec<ActiveSession>, anyhow::Error> {
+    let cutoffs = SessionCutoffs::now(settings);
+    sqlx::query_as!(
+        ActiveSession,
+        r#"
+SELECT session_id, created_at, last_seen_at, ip_address, user_agent
+FROM user_sessions
+WHERE user_id = $1
+    AND created_at > $2
+    AND CASE WHEN $3::boolean THEN last_seen_at ELSE created_at END
+        > CASE WHEN remember_me THEN $4::timestamptz ELSE $5::timestamptz END
+ORDER BY last_seen_at DESC
+"#,
+        user_id,
+        cutoffs.created_after,
+        cutoffs.rolling,
+        cutoffs.remembered_idle_after,
+        cutoffs.idle_after,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve sessions.")
+}
+
+/// Revoke one of `user_id`'s sessions, returning whether it existed.
+#[tracing::instrument(name = "Revoke session", skip(session_id, pool))]
+pub async fn revoke_session(
+    user_id: Uuid,
+    session_id: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM user_sessions WHERE user_id = $1 AND session_id = $2",
+        user_id,
+        session_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke the session.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Log the user out everywhere, except from `keep_session_id` if given.
+#[tracing::instrument(name = "Revoke sessions", skip(keep_session_id, pool))]
+pub async fn revoke_sessions(
+    user_id: Uuid,
+    keep_session_id: Option<&str>,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+DELETE FROM user_sessions
+WHERE user_id = $1 AND session_id IS DISTINCT FROM $2
+"#,
+        user_id,
+        keep_session_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke the user's sessions.")?;
+    Ok(())
+}
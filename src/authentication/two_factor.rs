@@ -0,0 +1,343 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use qrcode::QrCode;
+use qrcode::render::svg;
+use rand::{Rng, rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use totp_rs::{Builder, Totp};
+use uuid::Uuid;
+
+use crate::configuration::TwoFactorSettings;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Generate a fresh, base32 encoded TOTP secret.
+pub fn generate_totp_secret() -> Secret<String> {
+    Secret::new(totp_rs::Secret::generate().to_base32())
+}
+
+/// The `otpauth://` URI authenticator apps use to enroll `secret`.
+pub fn provisioning_uri(
+    settings: &TwoFactorSettings,
+    username: &str,
+    secret: &Secret<String>,
+) -> Result<String, anyhow::Error> {
+    totp(settings, username, secret)?
+        .to_url()
+        .context("Failed to build the TOTP provisioning URI.")
+}
+
+/// Render `uri` as an inline SVG QR code.
+pub fn qr_code_svg(uri: &str) -> Result<String, anyhow::Error> {
+    let code = QrCode::new(uri).context("Failed to encode the provisioning URI as a QR code.")?;
+    Ok(code.render::<svg::Color>().min_dimensions(200, 200).build())
+}
+
+/// Check `code` against `secret`, returning the time step it was generated for.
+pub fn check_totp_code(
+    settings: &TwoFactorSettings,
+    secret: &Secret<String>,
+    code: &str,
+) -> Result<Option<u64>, anyhow::Error> {
+    Ok(totp(settings, "", secret)?.check_current(code.trim()))
+}
+
+fn totp(
+    settings: &TwoFactorSettings,
+    username: &str,
+    secret: &Secret<String>,
+) -> Result<Totp, anyhow::Error> {
+    let secret = totp_rs::Secret::try_from_base32(secret.expose_secret())
+        .context("Failed to decode the TOTP secret.")?;
+    Builder::new()
+        .with_secret(secret)
+        .with_issuer(Some(settings.issuer.as_str()))
+        .with_account_name(username)
+        .build()
+        .context("Failed to build the TOTP generator.")
+}
+
+fn cipher(settings: &TwoFactorSettings) -> Aes256Gcm {
+    let key = Sha256::digest(settings.encryption_key.expose_secret().as_bytes());
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+}
+
+fn encrypt_secret(
+    settings: &TwoFactorSettings,
+    secret: &Secret<String>,
+) -> Result<String, anyhow::Error> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(settings)
+        .encrypt(&nonce, secret.expose_secret().as_bytes())
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt the TOTP secret."))?;
+    let mut stored = nonce.to_vec();
+    stored.extend(ciphertext);
+    Ok(STANDARD.encode(stored))
+}
+
+fn decrypt_secret(
+    settings: &TwoFactorSettings,
+    stored: &str,
+) -> Result<Secret<String>, anyhow::Error> {
+    let stored = STANDARD
+        .decode(stored)
+        .context("Failed to decode the stored TOTP secret.")?;
+    if stored.len() < 12 {
+        anyhow::bail!("The stored TOTP secret is truncated.");
+    }
+    let (nonce, ciphertext) = stored.split_at(12);
+    let plaintext = cipher(settings)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt the TOTP secret."))?;
+    Ok(Secret::new(
+        String::from_utf8(plaintext).context("The stored TOTP secret is not UTF-8.")?,
+    ))
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..10)
+                .map(|_| {
+                    RECOVERY_CODE_ALPHABET[rng.random_range(0..RECOVERY_CODE_ALPHABET.len())]
+                        as char
+                })
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    let normalised: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalised.as_bytes()))
+}
+
+#[tracing::instrument(name = "Check if two-factor authentication is enabled", skip(pool))]
+pub async fn has_two_factor(user_id: Uuid, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+SELECT totp_secret IS NOT NULL AS "enabled!"
+FROM users
+WHERE user_id = $1
+"#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve the two-factor status.")?;
+    Ok(row.enabled)
+}
+
+#[tracing::instrument(name = "Count unused recovery codes", skip(pool))]
+pub async fn remaining_recovery_codes(user_id: Uuid, pool: &PgPool) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+SELECT COUNT(*) AS "count!"
+FROM totp_recovery_codes
+WHERE user_id = $1 AND used_at IS NULL
+"#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to count recovery codes.")?;
+    Ok(row.count)
+}
+
+/// Store `secret` for `user_id`, marking the enrollment code's `step` as used,
+/// and return a fresh set of single-use recovery codes.
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(secret, pool, settings)
+)]
+pub async fn enable_two_factor(
+    user_id: Uuid,
+    secret: &Secret<String>,
+    step: u64,
+    pool: &PgPool,
+    settings: &TwoFactorSettings,
+) -> Result<Vec<String>, anyhow::Error> {
+    let encrypted_secret = encrypt_secret(settings, secret)?;
+    let recovery_codes = generate_recovery_codes();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+UPDATE users
+SET totp_secret = $1, totp_last_used_step = $2
+WHERE user_id = $3
+"#,
+        encrypted_secret,
+        step as i64,
+        user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the TOTP secret.")?;
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete old recovery codes.")?;
+    for code in &recovery_codes {
+        sqlx::query!(
+            r#"
+INSERT INTO totp_recovery_codes (user_id, code_hash)
+VALUES ($1, $2)
+"#,
+            user_id,
+            hash_recovery_code(code),
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to store a recovery code.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the two-factor enrollment.")?;
+    Ok(recovery_codes)
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(pool))]
+pub async fn disable_two_factor(user_id: Uuid, pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+UPDATE users
+SET totp_secret = NULL, totp_last_used_step = NULL
+WHERE user_id = $1
+"#,
+        user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to remove the TOTP secret.")?;
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete recovery codes.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit disabling two-factor authentication.")?;
+    Ok(())
+}
+
+/// Verify a TOTP code or, failing that, consume a recovery code. Each TOTP
+/// time step and each recovery code is only ever accepted once.
+#[tracing::instrument(name = "Verify second factor", skip(code, pool, settings))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+    settings: &TwoFactorSettings,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!("SELECT totp_secret FROM users WHERE user_id = $1", user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to perform a query to retrieve the TOTP secret.")?;
+    let Some(stored_secret) = row.totp_secret else {
+        return Ok(false);
+    };
+
+    let code = code.trim();
+    if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+        let secret = decrypt_secret(settings, &stored_secret)?;
+        let Some(step) = check_totp_code(settings, &secret, code)? else {
+            return Ok(false);
+        };
+        let result = sqlx::query!(
+            r#"
+UPDATE users
+SET totp_last_used_step = $1
+WHERE user_id = $2 AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+"#,
+            step as i64,
+            user_id,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the used TOTP step.")?;
+        return Ok(result.rows_affected() == 1);
+    }
+
+    let result = sqlx::query!(
+        r#"
+UPDATE totp_recovery_codes
+SET used_at = now()
+WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+"#,
+        user_id,
+        hash_recovery_code(code),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to consume a recovery code.")?;
+    if result.rows_affected() == 1 {
+        tracing::warn!("A recovery code was used to complete a login.");
+    }
+    Ok(result.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decrypt_secret, encrypt_secret, generate_totp_secret, hash_recovery_code};
+    use crate::configuration::TwoFactorSettings;
+    use secrecy::{ExposeSecret, Secret};
+
+    fn settings(key: &str) -> TwoFactorSettings {
+        TwoFactorSettings {
+            required: false,
+            issuer: "Test".into(),
+            encryption_key: Secret::new(key.into()),
+        }
+    }
+
+    #[test]
+    fn an_encrypted_secret_can_be_decrypted() {
+        let settings = settings("key");
+        let secret = generate_totp_secret();
+        let encrypted = encrypt_secret(&settings, &secret).unwrap();
+        assert_ne!(&encrypted, secret.expose_secret());
+        let decrypted = decrypt_secret(&settings, &encrypted).unwrap();
+        assert_eq!(decrypted.expose_secret(), secret.expose_secret());
+    }
+
+    #[test]
+    fn a_secret_cannot_be_decrypted_with_another_key() {
+        let secret = generate_totp_secret();
+        let encrypted = encrypt_secret(&settings("key"), &secret).unwrap();
+        assert!(decrypt_secret(&settings("another key"), &encrypted).is_err());
+    }
+
+    #[test]
+    fn recovery_codes_are_normalised_before_hashing() {
+        assert_eq!(
+            hash_recovery_code("abcde-fghjk"),
+            hash_recovery_code(" ABCDEFGHJK ")
+        );
+    }
+}
@@ -0,0 +1,23 @@
+use clap::Parser;
+use wizard_blog_backend::{
+    cli::{Cli, run},
+    configuration::get_configuration,
+    telemetry::{get_subscriber, init_subscriber},
+};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let configuration = get_configuration()?;
+
+    // Log to stderr, keeping stdout for output meant to be piped
+    let subscriber = get_subscriber(
+        "wizard-blog-admin".into(),
+        "warn".into(),
+        std::io::stderr,
+        None,
+    );
+    init_subscriber(subscriber);
+
+    run(cli, configuration).await
+}
@@ -0,0 +1,406 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::io::Read;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::audit::{AuditAction, record_cli_audit_event};
+use crate::authentication::{
+    Role, change_password, check_password_policy, create_user, revoke_sessions,
+};
+use crate::configuration::Settings;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::migrations::{migration_status, run_migrations};
+use crate::routes::{
+    deliver_newsletter, generate_subscription_token, send_confirmation_email, store_token,
+};
+use crate::startup::get_connection_pool;
+
+/// Operational tasks that would otherwise need psql. Reads the same
+/// configuration as the server, so run it with the same `APP_*` variables.
+#[derive(Parser)]
+#[command(name = "wizard_blog_admin")]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage the accounts of the admin area.
+    #[command(subcommand)]
+    Users(UsersCommand),
+    /// Inspect subscribers and chase unconfirmed ones.
+    #[command(subcommand)]
+    Subscribers(SubscribersCommand),
+    /// Manage the database schema.
+    #[command(subcommand)]
+    Migrations(MigrationsCommand),
+    /// Send newsletter issues.
+    #[command(subcommand)]
+    Newsletter(NewsletterCommand),
+}
+
+#[derive(Subcommand)]
+enum UsersCommand {
+    /// List every user.
+    List,
+    /// Create a user. The password is read from standard input.
+    Create {
+        #[arg(long)]
+        username: String,
+        #[arg(long, value_parser = Role::parse)]
+        role: Role,
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// Set a user's password and log them out everywhere. The password is
+    /// read from standard input.
+    ResetPassword {
+        #[arg(long)]
+        username: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SubscribersCommand {
+    /// Print every subscriber as one JSON object per line.
+    Dump,
+    /// Send a new confirmation link to subscribers who have not confirmed.
+    ResendConfirmations {
+        /// Only resend to this subscriber.
+        #[arg(long)]
+        email: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrationsCommand {
+    /// Apply the migrations the database is missing.
+    Run {
+        /// List the pending migrations without applying them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum NewsletterCommand {
+    /// Email an issue to every confirmed subscriber, e.g. after a failed
+    /// delivery.
+    Deliver {
+        #[arg(long)]
+        title: String,
+        #[arg(long)]
+        html_file: PathBuf,
+        #[arg(long)]
+        text_file: PathBuf,
+    },
+}
+
+pub async fn run(cli: Cli, configuration: Settings) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    match cli.command {
+        Command::Users(command) => run_users(command, &configuration, &pool).await,
+        Command::Subscribers(command) => run_subscribers(command, &configuration, &pool).await,
+        Command::Migrations(MigrationsCommand::Run { dry_run }) => {
+            run_migrations_command(dry_run, &configuration, &pool).await
+        }
+        Command::Newsletter(NewsletterCommand::Deliver {
+            title,
+            html_file,
+            text_file,
+        }) => {
+            let html_content = read_file(&html_file)?;
+            let text_content = read_file(&text_file)?;
+            let email_client = configuration.email_client.client()?;
+            deliver_newsletter(&title, &html_content, &text_content, &pool, &email_client).await?;
+            record_cli_audit_event(AuditAction::NewsletterPublished, Some(&title), &pool).await;
+            println!("The newsletter issue has been delivered.");
+            Ok(())
+        }
+    }
+}
+
+async fn run_users(
+    command: UsersCommand,
+    configuration: &Settings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    match command {
+        UsersCommand::List => {
+            for user in list_users(pool).await? {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    user.username,
+                    user.role,
+                    user.email.as_deref().unwrap_or("-"),
+                    if user.is_active { "active" } else { "inactive" },
+                );
+            }
+        }
+        UsersCommand::Create {
+            username,
+            role,
+            email,
+        } => {
+            let email = email
+                .map(SubscriberEmail::parse)
+                .transpose()
+                .map_err(anyhow::Error::msg)?;
+            if get_user_id(&username, pool).await?.is_some() {
+                anyhow::bail!("A user named {} already exists.", username);
+            }
+            let password = read_password(configuration, &username).await?;
+            create_user(
+                &username,
+                email.as_ref(),
+                password,
+                role,
+                &configuration.password_hashing,
+                pool,
+            )
+            .await?;
+            record_cli_audit_event(AuditAction::UserInvited, Some(&username), pool).await;
+            println!("Created {} as {}.", username, role);
+        }
+        UsersCommand::ResetPassword { username } => {
+            let user_id = get_user_id(&username, pool)
+                .await?
+                .with_context(|| format!("There is no user named {}.", username))?;
+            let password = read_password(configuration, &username).await?;
+            change_password(user_id, password, &configuration.password_hashing, pool).await?;
+            revoke_sessions(user_id, None, pool).await?;
+            record_cli_audit_event(AuditAction::PasswordReset, Some(&username), pool).await;
+            println!("Reset the password of {}.", username);
+        }
+    }
+    Ok(())
+}
+
+async fn run_subscribers(
+    command: SubscribersCommand,
+    configuration: &Settings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    match command {
+        SubscribersCommand::Dump => {
+            for subscriber in list_subscribers(pool).await? {
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "id": subscriber.id,
+                        "email": subscriber.email,
+                        "name": subscriber.name,
+                        "status": subscriber.status,
+                        "subscribed_at": subscriber.subscribed_at.to_rfc3339(),
+                    })
+                );
+            }
+        }
+        SubscribersCommand::ResendConfirmations { email } => {
+            let pending = list_pending_subscribers(email.as_deref(), pool).await?;
+            if pending.is_empty() && email.is_some() {
+                anyhow::bail!("There is no unconfirmed subscriber with this email.");
+            }
+            let email_client = configuration.email_client.client()?;
+            let mut sent = 0;
+            for subscriber in pending {
+                match subscriber {
+                    Ok((subscriber_id, subscriber)) => {
+                        resend_confirmation(
+                            subscriber_id,
+                            subscriber,
+                            &configuration.base_url,
+                            &email_client,
+                            pool,
+                        )
+                        .await?;
+                        sent += 1;
+                    }
+                    Err(error) => {
+                        tracing::warn!(error.cause_chain = ?error, "Skipping an unconfirmed subscriber. \
+                            Their stored contact details are invalid");
+                    }
+                }
+            }
+            println!("Sent {} confirmation emails.", sent);
+        }
+    }
+    Ok(())
+}
+
+async fn run_migrations_command(
+    dry_run: bool,
+    configuration: &Settings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let pending = if dry_run {
+        let mut connection = pool
+            .acquire()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool.")?;
+        let status = migration_status(&mut connection).await?;
+        status.ensure_not_ahead()?;
+        status.pending
+    } else {
+        run_migrations(&configuration.database).await?
+    };
+    for migration in &pending {
+        println!(
+            "{} {} {}",
+            if dry_run { "Pending" } else { "Applied" },
+            migration.version,
+            migration.description
+        );
+    }
+    if pending.is_empty() {
+        println!("The database is up to date.");
+    } else if dry_run {
+        println!("{} migrations would be applied.", pending.len());
+    } else {
+        println!("{} migrations applied.", pending.len());
+    }
+    Ok(())
+}
+
+/// Read the password from standard input, which keeps it out of the shell
+/// history, and hold it to the policy of the web forms.
+async fn read_password(
+    configuration: &Settings,
+    username: &str,
+) -> Result<Secret<String>, anyhow::Error> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .context("Failed to read the password from standard input.")?;
+    let password = Secret::new(input.trim_end_matches(['\r', '\n']).to_string());
+    let violations = check_password_policy(
+        &configuration.password_policy,
+        &password,
+        Some(username),
+        None,
+    )
+    .await?;
+    if !violations.is_empty() {
+        let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        anyhow::bail!(
+            "The password was rejected:\n  - {}",
+            violations.join("\n  - ")
+        );
+    }
+    Ok(password)
+}
+
+fn read_file(path: &PathBuf) -> Result<String, anyhow::Error> {
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+async fn resend_confirmation(
+    subscriber_id: Uuid,
+    subscriber: NewSubscriber,
+    base_url: &str,
+    email_client: &EmailClient,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let email = subscriber.email.as_ref().to_owned();
+    let token = generate_subscription_token();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    store_token(&mut transaction, subscriber_id, &token)
+        .await
+        .context("Failed to store the confirmation token for a new subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit database transaction.")?;
+    send_confirmation_email(email_client, subscriber, base_url, &token)
+        .await
+        .with_context(|| format!("Failed to send a confirmation email to {}", email))?;
+    Ok(())
+}
+
+struct UserRow {
+    username: String,
+    email: Option<String>,
+    role: String,
+    is_active: bool,
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+async fn get_user_id(username: &str, pool: &PgPool) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to perform a query to retrieve a user.")?;
+    Ok(row.map(|row| row.user_id))
+}
+
+async fn list_users(pool: &PgPool) -> Result<Vec<UserRow>, anyhow::Error> {
+    sqlx::query_as!(
+        UserRow,
+        r#"
+SELECT username, email, role, is_active
+FROM users
+ORDER BY username
+"#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve users.")
+}
+
+async fn list_subscribers(pool: &PgPool) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    sqlx::query_as!(
+        SubscriberRow,
+        r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+ORDER BY subscribed_at
+"#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscribers.")
+}
+
+/// Subscribers yet to confirm, optionally only the one with `email`.
+async fn list_pending_subscribers(
+    email: Option<&str>,
+    pool: &PgPool,
+) -> Result<Vec<Result<(Uuid, NewSubscriber), anyhow::Error>>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+SELECT id, email, name
+FROM subscriptions
+WHERE status = 'pending_confirmation' AND ($1::TEXT IS NULL OR lower(email) = lower($1))
+ORDER BY subscribed_at
+"#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve unconfirmed subscribers.")?;
+    let subscribers = rows
+        .into_iter()
+        .map(|row| {
+            let email = SubscriberEmail::parse(row.email).map_err(anyhow::Error::msg)?;
+            let name = SubscriberName::parse(row.name).map_err(anyhow::Error::msg)?;
+            Ok((row.id, NewSubscriber { email, name }))
+        })
+        .collect();
+    Ok(subscribers)
+}
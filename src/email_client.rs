@@ -1,13 +1,21 @@
+use opentelemetry::global;
+use opentelemetry::propagation::Injector;
 use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use secrecy::{ExposeSecret, Secret};
+use std::time::Instant;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::domain::SubscriberEmail;
+use crate::metrics::Metrics;
+use crate::request_id::current_request_id;
 #[derive(Debug)]
 pub struct EmailClient {
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
     auth_token: Secret<String>,
+    metrics: Option<Metrics>,
 }
 
 #[derive(serde::Serialize)]
@@ -18,6 +26,16 @@ pub struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader>,
+}
+
+/// A header added to the email itself, as opposed to the API call.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    name: String,
+    value: String,
 }
 
 impl EmailClient {
@@ -33,8 +51,25 @@ impl EmailClient {
             base_url,
             sender,
             auth_token,
+            metrics: None,
+        }
+    }
+
+    /// Record the latency and outcome of every send in `metrics`.
+    pub fn with_metrics(self, metrics: Metrics) -> Self {
+        Self {
+            metrics: Some(metrics),
+            ..self
         }
     }
+    /// Check that the provider answers at all. Any HTTP response counts, as
+    /// its API has no dedicated health endpoint.
+    pub async fn ping(&self) -> Result<(), reqwest::Error> {
+        self.http_client.head(&self.base_url).send().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Send email", skip_all, fields(subject = %subject))]
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
@@ -49,15 +84,53 @@ impl EmailClient {
             subject,
             html_body: html_content,
             text_body: text_content,
+            // Lets support trace a forwarded email back to the request that sent it
+            headers: current_request_id()
+                .map(|request_id| EmailHeader {
+                    name: "X-Request-Id".to_string(),
+                    value: request_id.to_string(),
+                })
+                .into_iter()
+                .collect(),
         };
-        self.http_client
+        let start = Instant::now();
+        let result = self
+            .http_client
             .post(&url)
             .header("X-POSTMARK-SERVER-TOKEN", self.auth_token.expose_secret())
+            .headers(trace_context_headers())
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_email_send(start.elapsed(), result.is_ok());
+        }
+        result.map(|_| ())
+    }
+}
+
+/// The `traceparent` of the current span, so the provider call can be
+/// followed as part of the same trace.
+fn trace_context_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
     }
 }
 
@@ -0,0 +1,209 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, CONTENT_TYPE, ContentType, Header, RETRY_AFTER};
+use actix_web::http::{StatusCode, header::HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, ResponseError};
+use htmlescape::encode_minimal;
+use std::time::Duration;
+
+use crate::request_id::current_request_id;
+use crate::utils::error_chain_fmt;
+
+/// What a handler can fail with. Messages of client errors are shown to the
+/// user, so they must not contain anything internal.
+#[derive(thiserror::Error)]
+pub enum AppError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{message}")]
+    TooManyRequests {
+        message: String,
+        retry_after: Duration,
+    },
+    #[error("Something went wrong.")]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The body is replaced by `render_error_pages`, which knows what the
+    /// client accepts.
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let AppError::TooManyRequests { retry_after, .. } = self {
+            response.insert_header((RETRY_AFTER, retry_after.as_secs().to_string()));
+        }
+        response
+            .content_type(ContentType::plaintext())
+            .body(self.to_string())
+    }
+}
+
+/// Answers requests no route matched.
+pub async fn page_not_found() -> Result<HttpResponse, AppError> {
+    Err(AppError::NotFound("This page does not exist.".into()))
+}
+
+#[derive(Clone, Copy)]
+enum ErrorFormat {
+    Html,
+    ProblemJson,
+}
+
+impl ErrorFormat {
+    /// Problem details for clients that rank JSON above HTML, a page for
+    /// everyone else.
+    fn negotiate(req: &ServiceRequest) -> Self {
+        let Ok(accept) = header::Accept::parse(req) else {
+            return ErrorFormat::Html;
+        };
+        accept
+            .ranked()
+            .iter()
+            .find_map(
+                |mime| match (mime.type_().as_str(), mime.subtype().as_str()) {
+                    ("text", "html") => Some(ErrorFormat::Html),
+                    ("application", "json") | ("application", "problem+json") => {
+                        Some(ErrorFormat::ProblemJson)
+                    }
+                    _ => None,
+                },
+            )
+            .unwrap_or(ErrorFormat::Html)
+    }
+}
+
+/// Render every error that does not bring its own page, whether it came from
+/// a handler or a middleware, as an HTML page or an RFC 7807 problem.
+///
+/// Server errors get a generic message and their cause chain is logged here,
+/// once. Must be layered outside `TracingLogger` and inside
+/// `propagate_request_id`.
+pub async fn render_error_pages(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let format = ErrorFormat::negotiate(&req);
+    match next.call(req).await {
+        Ok(response) => {
+            let (request, response) = response.map_into_boxed_body().into_parts();
+            let response = match response.error() {
+                Some(e) if has_default_body(&response) => {
+                    let rendered = render(e, response.status(), format);
+                    copy_headers(&response, rendered)
+                }
+                _ => response,
+            };
+            Ok(ServiceResponse::new(request, response))
+        }
+        Err(e) => {
+            let response = e.error_response();
+            if !has_default_body(&response) {
+                return Err(InternalError::from_response(e, response).into());
+            }
+            let rendered = copy_headers(&response, render(&e, response.status(), format));
+            Err(InternalError::from_response(e, rendered).into())
+        }
+    }
+}
+
+/// Pages rendered on purpose, such as redirects back to a form, are left
+/// alone. Anything else is actix's plain text fallback.
+fn has_default_body(response: &HttpResponse) -> bool {
+    let status = response.status();
+    (status.is_client_error() || status.is_server_error())
+        && response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_none_or(|value| value.starts_with("text/plain"))
+}
+
+/// Keep headers such as `Retry-After` and `WWW-Authenticate` of the original
+/// response.
+fn copy_headers(original: &HttpResponse, mut rendered: HttpResponse) -> HttpResponse {
+    for (name, value) in original.headers() {
+        if name != CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            rendered.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    rendered
+}
+
+/// `status` is taken from the response rather than `e`, as errors built
+/// from a response give it away the first time it is asked for.
+fn render(e: &actix_web::Error, status: StatusCode, format: ErrorFormat) -> HttpResponse {
+    let title = status.canonical_reason().unwrap_or("Error");
+    let detail = if status.is_server_error() {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "A request failed with a server error."
+        );
+        "Something went wrong on our side. Please try again later.".to_string()
+    } else {
+        e.to_string()
+    };
+    let request_id = current_request_id()
+        .map(|request_id| request_id.to_string())
+        .unwrap_or_default();
+
+    match format {
+        ErrorFormat::ProblemJson => HttpResponse::build(status)
+            .insert_header((
+                CONTENT_TYPE,
+                HeaderValue::from_static("application/problem+json"),
+            ))
+            .body(
+                serde_json::json!({
+                    "type": "about:blank",
+                    "title": title,
+                    "status": status.as_u16(),
+                    "detail": detail,
+                    "request_id": request_id,
+                })
+                .to_string(),
+            ),
+        ErrorFormat::Html => HttpResponse::build(status)
+            .content_type(ContentType::html())
+            .body(format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>{title} - Wizard Blog</title>
+</head>
+<body>
+<h1>{status_code} {title}</h1>
+<p>{detail}</p>
+<p>If you report this problem, please quote the request ID <code>{request_id}</code>.</p>
+<p><a href="/">Back to the blog</a></p>
+</body>
+</html>"#,
+                status_code = status.as_u16(),
+                detail = encode_minimal(&detail),
+                request_id = encode_minimal(&request_id),
+            )),
+    }
+}
@@ -1,7 +1,16 @@
+pub mod audit;
 pub mod authentication;
+pub mod background;
+pub mod cli;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod error;
+pub mod metrics;
+pub mod migrations;
+pub mod oidc_client;
+pub mod rate_limit;
+pub mod request_id;
 pub mod routes;
 pub mod session_state;
 pub mod startup;
@@ -1,22 +1,44 @@
 use wizard_blog_backend::{
     configuration::get_configuration,
     startup::Application,
-    telemetry::{get_subscriber, init_subscriber},
+    telemetry::{get_subscriber, get_tracer, init_otlp_tracer_provider, init_subscriber},
 };
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let configuration = get_configuration()?;
+    // Validate the configuration, e.g. before a deploy, without starting
+    if std::env::args().any(|arg| arg == "--check-config") {
+        println!("The configuration is valid.");
+        return Ok(());
+    }
+
+    let tracer_provider = configuration
+        .otlp
+        .as_ref()
+        .map(init_otlp_tracer_provider)
+        .transpose()?;
+
     // Set logging output to a file
     // let ts = Local::now().format("%Y-%m-%d_%H-%M-%S");
     // let log_file = format!("log_{}.txt", ts);
     // let file = File::create(&log_file)?;
-    let subscriber = get_subscriber("wizard-blog-backend".into(), "info".into(), std::io::stdout);
+    let subscriber = get_subscriber(
+        "wizard-blog-backend".into(),
+        "info".into(),
+        std::io::stdout,
+        tracer_provider.as_ref().map(get_tracer),
+    );
 
     init_subscriber(subscriber);
 
-    let configuration = get_configuration().expect("failed to read configuration.");
-
     let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+    let outcome = application.run_until_stopped().await;
+    if let Some(tracer_provider) = tracer_provider
+        && let Err(e) = tracer_provider.shutdown()
+    {
+        eprintln!("failed to flush traces: {}", e);
+    }
+    outcome?;
     Ok(())
 }
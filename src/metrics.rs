@@ -0,0 +1,248 @@
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::body::MessageBody;
+use actix_web::cookie::time::Duration as CookieDuration;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web;
+use anyhow::Context;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Prometheus metrics of one application instance, rendered by `GET /metrics`.
+/// Cloning is cheap and every clone updates the same series.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    db_pool_max_connections: IntGauge,
+    session_store_errors: IntCounterVec,
+    email_sends: IntCounterVec,
+    email_send_duration: Histogram,
+    subscribers: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled."),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to produce an HTTP response.",
+            ),
+            &["method", "route"],
+        )?;
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Open Postgres connections."),
+            &["state"],
+        )?;
+        let db_pool_max_connections = IntGauge::new(
+            "db_pool_max_connections",
+            "Most Postgres connections the pool opens.",
+        )?;
+        let session_store_errors = IntCounterVec::new(
+            Opts::new(
+                "session_store_errors_total",
+                "Failed operations against the Redis session store.",
+            ),
+            &["operation"],
+        )?;
+        let email_sends = IntCounterVec::new(
+            Opts::new("email_sends_total", "Emails handed to the email provider."),
+            &["outcome"],
+        )?;
+        let email_send_duration = Histogram::with_opts(HistogramOpts::new(
+            "email_send_duration_seconds",
+            "Time taken by the email provider to accept an email.",
+        ))?;
+        let subscribers = IntGaugeVec::new(
+            Opts::new("subscribers", "Newsletter subscribers."),
+            &["status"],
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(db_pool_max_connections.clone()))?;
+        registry.register(Box::new(session_store_errors.clone()))?;
+        registry.register(Box::new(email_sends.clone()))?;
+        registry.register(Box::new(email_send_duration.clone()))?;
+        registry.register(Box::new(subscribers.clone()))?;
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_pool_connections,
+            db_pool_max_connections,
+            session_store_errors,
+            email_sends,
+            email_send_duration,
+            subscribers,
+        })
+    }
+
+    pub fn observe_email_send(&self, elapsed: Duration, succeeded: bool) {
+        self.email_send_duration.observe(elapsed.as_secs_f64());
+        let outcome = if succeeded { "success" } else { "failure" };
+        self.email_sends.with_label_values(&[outcome]).inc();
+    }
+
+    /// Encode every series in the Prometheus text format, refreshing the
+    /// gauges that are sampled rather than updated as things happen.
+    #[tracing::instrument(name = "Render metrics", skip_all)]
+    pub async fn render(&self, pool: &PgPool) -> Result<String, anyhow::Error> {
+        let idle = pool.num_idle() as i64;
+        self.db_pool_connections
+            .with_label_values(&["idle"])
+            .set(idle);
+        self.db_pool_connections
+            .with_label_values(&["in_use"])
+            .set(i64::from(pool.size()) - idle);
+        self.db_pool_max_connections
+            .set(i64::from(pool.options().get_max_connections()));
+
+        let counts = sqlx::query!(
+            r#"SELECT status, COUNT(*) AS "count!" FROM subscriptions GROUP BY status"#
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to count subscribers by status.")?;
+        // Statuses nobody has any more should drop to zero rather than linger
+        self.subscribers.reset();
+        for row in counts {
+            self.subscribers
+                .with_label_values(&[&row.status])
+                .set(row.count);
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .context("Failed to encode metrics.")?;
+        String::from_utf8(buffer).context("Metrics are not valid UTF-8.")
+    }
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+/// Count and time every request by its route pattern, so that path
+/// parameters do not explode the number of series.
+pub async fn record_http_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let start = Instant::now();
+    let result = next.call(req).await;
+    if let Some(metrics) = metrics {
+        let status = match &result {
+            Ok(response) => response.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        metrics
+            .http_requests
+            .with_label_values(&[&method, &route, status.as_str()])
+            .inc();
+        metrics
+            .http_request_duration
+            .with_label_values(&[&method, &route])
+            .observe(start.elapsed().as_secs_f64());
+    }
+    result
+}
+
+/// Wraps a session store to count its failures, which actix-session
+/// otherwise only turns into 500s.
+pub struct InstrumentedSessionStore<S> {
+    inner: S,
+    metrics: Metrics,
+}
+
+impl<S> InstrumentedSessionStore<S> {
+    pub fn new(inner: S, metrics: Metrics) -> Self {
+        Self { inner, metrics }
+    }
+
+    fn count_error(&self, operation: &str) {
+        self.metrics
+            .session_store_errors
+            .with_label_values(&[operation])
+            .inc();
+    }
+}
+
+impl<S: SessionStore> SessionStore for InstrumentedSessionStore<S> {
+    async fn load(
+        &self,
+        session_key: &SessionKey,
+    ) -> Result<Option<HashMap<String, String>>, LoadError> {
+        let result = self.inner.load(session_key).await;
+        if result.is_err() {
+            self.count_error("load");
+        }
+        result
+    }
+
+    async fn save(
+        &self,
+        session_state: HashMap<String, String>,
+        ttl: &CookieDuration,
+    ) -> Result<SessionKey, SaveError> {
+        let result = self.inner.save(session_state, ttl).await;
+        if result.is_err() {
+            self.count_error("save");
+        }
+        result
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: HashMap<String, String>,
+        ttl: &CookieDuration,
+    ) -> Result<SessionKey, UpdateError> {
+        let result = self.inner.update(session_key, session_state, ttl).await;
+        if result.is_err() {
+            self.count_error("update");
+        }
+        result
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &CookieDuration,
+    ) -> Result<(), anyhow::Error> {
+        let result = self.inner.update_ttl(session_key, ttl).await;
+        if result.is_err() {
+            self.count_error("update_ttl");
+        }
+        result
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        let result = self.inner.delete(session_key).await;
+        if result.is_err() {
+            self.count_error("delete");
+        }
+        result
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::Context;
+use sqlx::migrate::Migrator;
+use sqlx::{Connection, PgConnection, PgPool};
+use std::collections::HashSet;
+
+use crate::configuration::DatabaseSettings;
+
+/// The migrations of `migrations/`, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Held while migrating, so that replicas starting together take turns and
+/// the ones coming second find nothing left to do.
+const MIGRATION_LOCK_KEY: i64 = 7_301_946_520_183_349_049;
+
+/// An embedded migration the database has not applied yet.
+#[derive(Debug)]
+pub struct PendingMigration {
+    pub version: i64,
+    pub description: String,
+}
+
+/// How the schema of the database compares to the one the binary expects.
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub pending: Vec<PendingMigration>,
+    /// Applied migrations this binary knows nothing about, left behind by a
+    /// newer release.
+    pub unknown: Vec<i64>,
+}
+
+impl MigrationStatus {
+    /// A newer release migrated the database, so this one may rely on
+    /// columns or tables that have since changed.
+    pub fn ensure_not_ahead(&self) -> Result<(), anyhow::Error> {
+        if !self.unknown.is_empty() {
+            let versions: Vec<String> = self.unknown.iter().map(i64::to_string).collect();
+            anyhow::bail!(
+                "The database has migrations this binary does not know about ({}). \
+                Deploy a release that includes them instead.",
+                versions.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Compare the migrations applied to the database with the embedded ones.
+#[tracing::instrument(name = "Get migration status", skip(connection))]
+pub async fn migration_status(
+    connection: &mut PgConnection,
+) -> Result<MigrationStatus, anyhow::Error> {
+    // The table is created by the first migration run
+    let has_table: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(&mut *connection)
+        .await
+        .context("Failed to look for the migrations table")?;
+    let applied: HashSet<i64> = if has_table {
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(&mut *connection)
+            .await
+            .context("Failed to list the applied migrations")?
+            .into_iter()
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let pending = MIGRATOR
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .map(|migration| PendingMigration {
+            version: migration.version,
+            description: migration.description.to_string(),
+        })
+        .collect();
+    let mut unknown: Vec<i64> = applied
+        .into_iter()
+        .filter(|version| !MIGRATOR.version_exists(*version))
+        .collect();
+    unknown.sort_unstable();
+    Ok(MigrationStatus { pending, unknown })
+}
+
+/// Apply the pending migrations, returning them. Refuses to touch a
+/// database that is ahead of the binary.
+#[tracing::instrument(name = "Run migrations", skip(configuration))]
+pub async fn run_migrations(
+    configuration: &DatabaseSettings,
+) -> Result<Vec<PendingMigration>, anyhow::Error> {
+    // A connection of its own, as closing it is what releases the lock
+    let mut connection = PgConnection::connect_with(&configuration.with_db())
+        .await
+        .context("Failed to connect to Postgres to migrate")?;
+    // Migrations rewriting big tables may rightly take longer than queries
+    sqlx::query("SET statement_timeout = 0")
+        .execute(&mut connection)
+        .await
+        .context("Failed to lift the statement timeout")?;
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut connection)
+        .await
+        .context("Failed to take the migration lock")?;
+    let outcome = apply_pending_migrations(&mut connection).await;
+    connection
+        .close()
+        .await
+        .context("Failed to release the migration lock")?;
+    outcome
+}
+
+async fn apply_pending_migrations(
+    connection: &mut PgConnection,
+) -> Result<Vec<PendingMigration>, anyhow::Error> {
+    let status = migration_status(connection).await?;
+    status.ensure_not_ahead()?;
+    MIGRATOR
+        .run(&mut *connection)
+        .await
+        .context("Failed to apply the pending migrations")?;
+    for migration in &status.pending {
+        tracing::info!(
+            migration.version = migration.version,
+            migration.description = %migration.description,
+            "Applied a migration."
+        );
+    }
+    Ok(status.pending)
+}
+
+/// Refuse to run against a database a newer release has migrated.
+pub async fn check_database_is_not_ahead(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    migration_status(&mut connection).await?.ensure_not_ahead()
+}
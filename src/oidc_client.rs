@@ -0,0 +1,217 @@
+use anyhow::Context;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+use crate::configuration::OidcSettings;
+
+/// Signing algorithms accepted for ID tokens. Symmetric ones are left out on
+/// purpose: anyone knowing the client secret could forge tokens with them.
+const ALLOWED_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// Talks to the OpenID Connect provider admins log in with, using the
+/// authorization code flow with PKCE.
+#[derive(Debug)]
+pub struct OidcClient {
+    http_client: Client,
+    settings: OidcSettings,
+    redirect_uri: String,
+}
+
+/// The endpoints advertised by the provider's discovery document.
+#[derive(serde::Deserialize, Debug)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// The ID token claims used to find the matching user.
+#[derive(serde::Deserialize, Debug)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+impl OidcClient {
+    pub fn new(settings: OidcSettings, base_url: &str, timeout: std::time::Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            redirect_uri: format!("{}/login/oidc/callback", base_url.trim_end_matches('/')),
+            settings,
+        }
+    }
+
+    /// The name of the provider shown on the login page.
+    pub fn display_name(&self) -> &str {
+        &self.settings.display_name
+    }
+
+    pub fn link_by_email(&self) -> bool {
+        self.settings.link_by_email
+    }
+
+    pub fn trust_provider_mfa(&self) -> bool {
+        self.settings.trust_provider_mfa
+    }
+
+    pub fn issuer(&self) -> &str {
+        self.settings.issuer_url.trim_end_matches('/')
+    }
+
+    #[tracing::instrument(name = "Discover OIDC provider", skip(self))]
+    pub async fn discover(&self) -> Result<ProviderMetadata, anyhow::Error> {
+        let url = format!("{}/.well-known/openid-configuration", self.issuer());
+        let metadata: ProviderMetadata = self
+            .http_client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed to fetch the OIDC discovery document.")?
+            .json()
+            .await
+            .context("Failed to parse the OIDC discovery document.")?;
+        if metadata.issuer.trim_end_matches('/') != self.issuer() {
+            anyhow::bail!("The OIDC discovery document is for another issuer.");
+        }
+        Ok(metadata)
+    }
+
+    /// Where to send the user to authenticate with the provider.
+    pub fn authorization_url(
+        &self,
+        metadata: &ProviderMetadata,
+        state: &str,
+        nonce: &str,
+        code_verifier: &Secret<String>,
+    ) -> Result<String, anyhow::Error> {
+        let mut url = reqwest::Url::parse(&metadata.authorization_endpoint)
+            .context("The OIDC authorization endpoint is not a valid URL.")?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.settings.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", "openid email")
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &pkce_challenge(code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.into())
+    }
+
+    /// Redeem the authorization code for a verified set of ID token claims.
+    #[tracing::instrument(name = "Exchange OIDC authorization code", skip_all)]
+    pub async fn exchange_code(
+        &self,
+        metadata: &ProviderMetadata,
+        code: &str,
+        code_verifier: &Secret<String>,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, anyhow::Error> {
+        let response: TokenResponse = self
+            .http_client
+            .post(&metadata.token_endpoint)
+            .basic_auth(
+                &self.settings.client_id,
+                Some(self.settings.client_secret.expose_secret()),
+            )
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("code_verifier", code_verifier.expose_secret()),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed to redeem the OIDC authorization code.")?
+            .json()
+            .await
+            .context("Failed to parse the OIDC token response.")?;
+        let claims = self.verify_id_token(metadata, &response.id_token).await?;
+        if claims.nonce.as_deref() != Some(nonce) {
+            anyhow::bail!("The ID token nonce does not match the login attempt.");
+        }
+        Ok(claims)
+    }
+
+    async fn verify_id_token(
+        &self,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+    ) -> Result<IdTokenClaims, anyhow::Error> {
+        let header = jsonwebtoken::decode_header(id_token).context("Malformed ID token.")?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            anyhow::bail!("ID tokens signed with {:?} are not accepted.", header.alg);
+        }
+        let keys: JwkSet = self
+            .http_client
+            .get(&metadata.jwks_uri)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed to fetch the OIDC signing keys.")?
+            .json()
+            .await
+            .context("Failed to parse the OIDC signing keys.")?;
+        let key = match &header.kid {
+            Some(kid) => keys.find(kid),
+            None => keys.keys.first(),
+        }
+        .context("The ID token was signed with an unknown key.")?;
+        let key = DecodingKey::from_jwk(key).context("Unsupported OIDC signing key.")?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.settings.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let token = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+            .context("The ID token is invalid.")?;
+        Ok(token.claims)
+    }
+}
+
+/// The S256 PKCE challenge for `code_verifier`.
+pub fn pkce_challenge(code_verifier: &Secret<String>) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.expose_secret().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pkce_challenge;
+    use secrecy::Secret;
+
+    #[test]
+    fn pkce_challenge_is_the_unpadded_base64url_sha256_of_the_verifier() {
+        let verifier = Secret::new("dBjftJeZ4CVP-mB92K2uhbUOFhtKsBQvAdsbmNOGKX8".to_string());
+        assert_eq!(
+            pkce_challenge(&verifier),
+            "kltp_ISmwz63__GBm3sl4_7LPzdJ7CdtsTK3_yCmGvU"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+/// Fixed-window attempt counters stored in Redis.
+#[derive(Clone)]
+pub struct RateLimiter {
+    connection: ConnectionManager,
+    key_prefix: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RateLimitDecision {
+    Allowed { attempts: u64 },
+    Limited { retry_after: Duration },
+}
+
+impl RateLimiter {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        key_prefix: String,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(Self {
+            connection,
+            key_prefix,
+        })
+    }
+
+    /// Check that the Redis server behind the limiter answers.
+    pub async fn ping(&self) -> Result<(), redis::RedisError> {
+        let mut connection = self.connection.clone();
+        redis::cmd("PING").query_async(&mut connection).await
+    }
+
+    /// Record an attempt against `key` and decide whether it is still within
+    /// `max_attempts` for the current `window`.
+    #[tracing::instrument(name = "Check rate limit", skip(self))]
+    pub async fn hit(
+        &self,
+        key: &str,
+        max_attempts: u64,
+        window: Duration,
+    ) -> Result<RateLimitDecision, redis::RedisError> {
+        let key = self.key(key);
+        let mut connection = self.connection.clone();
+        let (_, attempts, ttl): (Option<String>, u64, i64) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("NX")
+            .arg("EX")
+            .arg(window.as_secs().max(1))
+            .cmd("INCR")
+            .arg(&key)
+            .cmd("TTL")
+            .arg(&key)
+            .query_async(&mut connection)
+            .await?;
+        if attempts > max_attempts {
+            let retry_after = Duration::from_secs(ttl.max(1) as u64);
+            Ok(RateLimitDecision::Limited { retry_after })
+        } else {
+            Ok(RateLimitDecision::Allowed { attempts })
+        }
+    }
+
+    /// Forget all attempts recorded against `key`.
+    #[tracing::instrument(name = "Reset rate limit", skip(self))]
+    pub async fn reset(&self, key: &str) -> Result<(), redis::RedisError> {
+        let mut connection = self.connection.clone();
+        redis::cmd("DEL")
+            .arg(self.key(key))
+            .query_async::<()>(&mut connection)
+            .await
+    }
+
+    /// Block `key` for `duration`, regardless of the attempts recorded so far.
+    #[tracing::instrument(name = "Lock rate limit key", skip(self))]
+    pub async fn lock(&self, key: &str, duration: Duration) -> Result<(), redis::RedisError> {
+        let mut connection = self.connection.clone();
+        redis::cmd("SET")
+            .arg(self.key(key))
+            .arg(1)
+            .arg("EX")
+            .arg(duration.as_secs().max(1))
+            .query_async::<()>(&mut connection)
+            .await
+    }
+
+    /// How much longer `key` stays locked, if it is locked at all.
+    #[tracing::instrument(name = "Check rate limit lock", skip(self))]
+    pub async fn locked_for(&self, key: &str) -> Result<Option<Duration>, redis::RedisError> {
+        let mut connection = self.connection.clone();
+        let ttl: i64 = redis::cmd("TTL")
+            .arg(self.key(key))
+            .query_async(&mut connection)
+            .await?;
+        Ok((ttl > 0).then(|| Duration::from_secs(ttl as u64)))
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.key_prefix, key)
+    }
+}
@@ -0,0 +1,156 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use tracing::Span;
+use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest `X-Request-Id` accepted from upstream, to keep logs and rows sane.
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// Identifies one request across the response, the logs, the rows it creates
+/// and the emails it sends.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Honour an id assigned by a proxy in front of us, as long as it is
+    /// short and made of characters that are safe to log and echo.
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= MAX_LENGTH
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+        valid.then(|| Self(value.to_string()))
+    }
+
+    fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The id of the request being handled, if any. Background tasks run outside
+/// of any request.
+pub fn current_request_id() -> Option<RequestId> {
+    CURRENT.try_with(RequestId::clone).ok()
+}
+
+/// Assign every request an id and echo it in the response.
+///
+/// Must wrap `TracingLogger`, which picks the id up from the request
+/// extensions through `RequestIdRootSpanBuilder`.
+pub async fn propagate_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(RequestId::from_header)
+        .unwrap_or_else(RequestId::generate);
+    req.extensions_mut().insert(request_id.clone());
+
+    match CURRENT.scope(request_id.clone(), next.call(req)).await {
+        Ok(mut response) => {
+            tag_response(response.response_mut(), &request_id);
+            Ok(response.map_into_boxed_body())
+        }
+        // Errors raised by inner middleware carry their response along
+        Err(e) => {
+            let mut response = e.error_response();
+            tag_response(&mut response, &request_id);
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+fn tag_response<B>(response: &mut HttpResponse<B>, request_id: &RequestId) {
+    response.headers_mut().insert(
+        REQUEST_ID_HEADER,
+        HeaderValue::from_str(request_id.as_str()).expect("Request ids are valid header values"),
+    );
+}
+
+/// `TracingLogger`'s default root span, carrying our request id rather than
+/// the one it generates itself. Cause chains are left out, as
+/// `render_error_pages` logs them.
+pub struct RequestIdRootSpanBuilder;
+
+impl RootSpanBuilder for RequestIdRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let span = DefaultRootSpanBuilder::on_request_start(request);
+        if let Some(request_id) = request.extensions().get::<RequestId>() {
+            span.record("request_id", tracing::field::display(request_id));
+        }
+        span
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        let (status, error) = match outcome {
+            Ok(response) => (response.status(), response.response().error()),
+            Err(e) => (e.as_response_error().status_code(), Some(e)),
+        };
+        if let Some(e) = error {
+            span.record("exception.message", tracing::field::display(e));
+        }
+        span.record("http.status_code", status.as_u16());
+        span.record(
+            "otel.status_code",
+            if status.is_server_error() {
+                "ERROR"
+            } else {
+                "OK"
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestId;
+    use actix_web::http::header::HeaderValue;
+
+    #[test]
+    fn upstream_ids_are_honoured_only_when_safe() {
+        let accepted = [
+            "abc-123",
+            "1f0c2a3e-5b7d-4c1e-9f3a-2b4c6d8e0f1a",
+            "lb:42.7_a",
+        ];
+        for id in accepted {
+            assert_eq!(
+                RequestId::from_header(&HeaderValue::from_static(id)).map(|id| id.0),
+                Some(id.to_string())
+            );
+        }
+        let long = "a".repeat(129);
+        let rejected = ["", "has space", "<script>", "new\tline", long.as_str()];
+        for id in rejected {
+            assert_eq!(
+                RequestId::from_header(&HeaderValue::from_str(id).unwrap()),
+                None
+            );
+        }
+    }
+}
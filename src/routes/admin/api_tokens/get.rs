@@ -0,0 +1,102 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::authentication::{Permission, Role, UserId, list_api_tokens};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+#[tracing::instrument(name = "Get API tokens list", skip(session, flash_messages, pool))]
+pub async fn api_tokens_list(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = *user_id.into_inner();
+    let role = role.into_inner();
+    let csrf_token = session.csrf_token().map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap()
+    }
+
+    let tokens = list_api_tokens(user_id, &pool).await.map_err(e500)?;
+    let mut rows = String::new();
+    for token in tokens {
+        writeln!(
+            rows,
+            r#"<tr><td>{name}</td><td>{scopes}</td><td>{created}</td><td>{expires}</td><td>{last_used}</td><td><form action="/admin/api-tokens/{id}/revoke" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<button type="submit">Revoke</button>
+</form></td></tr>"#,
+            name = encode_minimal(&token.name),
+            scopes = encode_minimal(&token.scopes.join(", ")),
+            created = format_time(Some(token.created_at), "-"),
+            expires = format_time(token.expires_at, "Never"),
+            last_used = format_time(token.last_used_at, "Never"),
+            id = token.token_id,
+        )
+        .unwrap()
+    }
+    let mut scope_options = String::new();
+    for permission in Permission::ALL.into_iter().filter(|p| role.can(*p)) {
+        writeln!(
+            scope_options,
+            r#"<label><input type="checkbox" name="scopes" value="{scope}"> {scope}</label>"#,
+            scope = permission.as_str(),
+        )
+        .unwrap()
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>API tokens</title>
+</head>
+<body>
+{msg_html}
+<table>
+<tr><th>Name</th><th>Scopes</th><th>Created</th><th>Expires</th><th>Last used</th><th>Actions</th></tr>
+{rows}
+</table>
+<h2>Create a token</h2>
+<form action="/admin/api-tokens" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<label>Name
+<input type="text" placeholder="What the token is for" name="name">
+</label>
+<fieldset>
+<legend>Scopes</legend>
+{scope_options}
+</fieldset>
+<label>Expires
+<select name="expires_in_days">
+<option value="30">In 30 days</option>
+<option value="90" selected>In 90 days</option>
+<option value="365">In a year</option>
+<option value="">Never</option>
+</select>
+</label>
+<button type="submit">Create token</button>
+</form>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+fn format_time(time: Option<DateTime<Utc>>, missing: &str) -> String {
+    match time {
+        Some(time) => time.format("%Y-%m-%d %H:%M UTC").to_string(),
+        None => missing.to_string(),
+    }
+}
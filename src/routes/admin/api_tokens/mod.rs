@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::api_tokens_list;
+pub use post::*;
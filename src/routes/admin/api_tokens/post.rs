@@ -0,0 +1,129 @@
+use actix_web::{HttpRequest, HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::FlashMessage;
+use chrono::{Duration, Utc};
+use htmlescape::encode_minimal;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+use crate::audit::{AuditAction, record_audit_event};
+use crate::authentication::{Permission, Role, UserId, create_api_token, revoke_api_token};
+use crate::utils::{e500, see_other};
+
+/// Longest expiry that can be picked, in days.
+const MAX_EXPIRY_DAYS: i64 = 3650;
+
+/// The form repeats the `scopes` field once per ticked checkbox, which only a
+/// list of pairs can represent.
+#[tracing::instrument(name = "Create an API token", skip(form, request, pool))]
+pub async fn create_token(
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let role = role.into_inner();
+    let mut name = "";
+    let mut expires_in_days = "";
+    let mut scopes = Vec::new();
+    for (key, value) in form.iter() {
+        match key.as_str() {
+            "name" => name = value.trim(),
+            "expires_in_days" => expires_in_days = value.trim(),
+            "scopes" => match Permission::parse(value) {
+                Ok(permission) if role.can(permission) => {
+                    if !scopes.contains(&permission) {
+                        scopes.push(permission)
+                    }
+                }
+                _ => {
+                    FlashMessage::error("Please pick scopes your role allows.").send();
+                    return Ok(see_other("/admin/api-tokens"));
+                }
+            },
+            _ => {}
+        }
+    }
+    if name.is_empty() || name.graphemes(true).count() > 100 {
+        FlashMessage::error("Token names must be between 1 and 100 characters long.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    if scopes.is_empty() {
+        FlashMessage::error("Please pick at least one scope.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    let expires_at = if expires_in_days.is_empty() {
+        None
+    } else {
+        match expires_in_days.parse::<i64>() {
+            Ok(days) if (1..=MAX_EXPIRY_DAYS).contains(&days) => {
+                Some(Utc::now() + Duration::days(days))
+            }
+            _ => {
+                FlashMessage::error("Please pick a valid expiry.").send();
+                return Ok(see_other("/admin/api-tokens"));
+            }
+        }
+    };
+
+    let token = create_api_token(**user_id, name, &scopes, expires_at, &pool)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        Some(**user_id),
+        AuditAction::ApiTokenCreated,
+        Some(name),
+        &request,
+        &pool,
+    )
+    .await;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>API token created</title>
+</head>
+<body>
+<p>The {name} token has been created.</p>
+<p>Send it in an <code>Authorization: Bearer</code> header: <code>{token}</code></p>
+<p>It will not be shown again.</p>
+<p><a href="/admin/api-tokens">&lt;- Back</a></p>
+</body>
+</html>"#,
+            name = encode_minimal(name),
+            token = token.expose_secret(),
+        )))
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(request, pool))]
+pub async fn revoke_token(
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token_id = path.into_inner();
+    if revoke_api_token(**user_id, token_id, &pool)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            Some(**user_id),
+            AuditAction::ApiTokenRevoked,
+            Some(&token_id.to_string()),
+            &request,
+            &pool,
+        )
+        .await;
+        FlashMessage::info("The API token has been revoked.").send();
+    } else {
+        FlashMessage::error("The API token does not exist.").send();
+    }
+    Ok(see_other("/admin/api-tokens"))
+}
@@ -0,0 +1,164 @@
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{HttpResponse, web};
+use chrono::{NaiveDate, TimeDelta};
+use htmlescape::encode_minimal;
+use std::fmt::Write;
+
+use crate::audit::{AuditAction, AuditFilter, list_audit_events};
+use crate::error::AppError;
+use crate::startup::ReadPool;
+use crate::utils::e500;
+
+/// How many events the page shows. The export has no limit.
+const PAGE_LIMIT: i64 = 200;
+
+/// Empty fields are submitted by the filter form and match everything.
+#[derive(serde::Deserialize)]
+pub struct QueryParameters {
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    actor: String,
+    #[serde(default)]
+    from: String,
+    #[serde(default)]
+    to: String,
+}
+
+impl QueryParameters {
+    fn to_filter(&self) -> Result<AuditFilter, AppError> {
+        let action = match self.action.trim() {
+            "" => None,
+            action => Some(AuditAction::parse(action).map_err(AppError::BadRequest)?),
+        };
+        let actor_username = Some(self.actor.trim())
+            .filter(|actor| !actor.is_empty())
+            .map(String::from);
+        // Both ends are whole days, `to` included
+        let since = parse_date(&self.from)?.map(|date| date.and_time(Default::default()).and_utc());
+        let until = parse_date(&self.to)?
+            .and_then(|date| date.checked_add_signed(TimeDelta::days(1)))
+            .map(|date| date.and_time(Default::default()).and_utc());
+        Ok(AuditFilter {
+            action,
+            actor_username,
+            since,
+            until,
+        })
+    }
+
+    fn query_string(&self) -> String {
+        [
+            ("action", &self.action),
+            ("actor", &self.actor),
+            ("from", &self.from),
+            ("to", &self.to),
+        ]
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value.trim())))
+        .collect::<Vec<_>>()
+        .join("&")
+    }
+}
+
+fn parse_date(date: &str) -> Result<Option<NaiveDate>, AppError> {
+    match date.trim() {
+        "" => Ok(None),
+        date => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| AppError::BadRequest("Dates must be formatted as YYYY-MM-DD.".into())),
+    }
+}
+
+#[tracing::instrument(name = "Get audit log", skip(parameters, pool))]
+pub async fn audit_log(
+    parameters: web::Query<QueryParameters>,
+    pool: web::Data<ReadPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = parameters.to_filter()?;
+    let events = list_audit_events(&filter, Some(PAGE_LIMIT), &pool)
+        .await
+        .map_err(e500)?;
+    let mut rows = String::new();
+    for event in &events {
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            event.occurred_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            encode_minimal(event.actor_username.as_deref().unwrap_or("-")),
+            encode_minimal(&event.action),
+            encode_minimal(event.target.as_deref().unwrap_or("-")),
+            encode_minimal(event.ip_address.as_deref().unwrap_or("-")),
+            encode_minimal(event.user_agent.as_deref().unwrap_or("-")),
+        )
+        .unwrap()
+    }
+    let mut action_options = String::from(r#"<option value="">Any action</option>"#);
+    for action in AuditAction::ALL {
+        let selected = if filter.action == Some(action) {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            action_options,
+            r#"<option value="{0}"{selected}>{0}</option>"#,
+            action.as_str()
+        )
+        .unwrap()
+    }
+    let truncated = if events.len() as i64 == PAGE_LIMIT {
+        format!("<p>Only the {PAGE_LIMIT} most recent matching events are shown.</p>")
+    } else {
+        String::new()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Audit log</title>
+</head>
+<body>
+<form action="/admin/audit" method="get">
+<label>Action <select name="action">{action_options}</select></label>
+<label>Actor <input type="text" name="actor" value="{actor}"></label>
+<label>From <input type="date" name="from" value="{from}"></label>
+<label>To <input type="date" name="to" value="{to}"></label>
+<button type="submit">Filter</button>
+</form>
+{truncated}
+<table>
+<tr><th>Time</th><th>Actor</th><th>Action</th><th>Target</th><th>IP address</th><th>Browser</th></tr>
+{rows}
+</table>
+<p><a href="/admin/audit/export?{export_query}">Export as JSON</a></p>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            actor = encode_minimal(parameters.actor.trim()),
+            from = encode_minimal(parameters.from.trim()),
+            to = encode_minimal(parameters.to.trim()),
+            export_query = encode_minimal(&parameters.query_string()),
+        )))
+}
+
+#[tracing::instrument(name = "Export audit log", skip(parameters, pool))]
+pub async fn export_audit_log(
+    parameters: web::Query<QueryParameters>,
+    pool: web::Data<ReadPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = parameters.to_filter()?;
+    let events = list_audit_events(&filter, None, &pool)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("audit-log.json".into())],
+        })
+        .json(events))
+}
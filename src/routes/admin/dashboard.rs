@@ -4,6 +4,7 @@ use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::{
+    authentication::{Permission, Role},
     session_state::TypedSession,
     utils::{e500, see_other},
 };
@@ -12,12 +13,35 @@ use crate::{
 pub async fn admin_dashboard(
     session: TypedSession,
     pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let username = if let Some(user_id) = session.get_user_id().map_err(e500)? {
         get_username(user_id, &pool).await.map_err(e500)?
     } else {
         return Ok(see_other("/login"));
     };
+    let csrf_token = session.csrf_token().map_err(e500)?;
+    let role = role.into_inner();
+    let mut actions = String::from(r#"<li><a href="/admin/password">Change password</a></li>"#);
+    let links = [
+        (
+            Permission::DraftNewsletters,
+            "/admin/newsletters",
+            "Newsletters",
+        ),
+        (
+            Permission::ViewSubscribers,
+            "/admin/subscribers",
+            "Subscribers",
+        ),
+        (Permission::ManageUsers, "/admin/users", "Users"),
+        (Permission::ViewAuditLog, "/admin/audit", "Audit log"),
+    ];
+    for (permission, href, label) in links {
+        if role.can(permission) {
+            actions.push_str(&format!(r#"<li><a href="{href}">{label}</a></li>"#));
+        }
+    }
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -29,11 +53,15 @@ pub async fn admin_dashboard(
         </head>
         <body>
         <p>Welcome {username}!</p>
+        <p>Your role: {role}</p>
         <p>Available actions:</p>
         <ol>
-        <li><a href="/admin/password">Change password</a></li>
-        <li><a href="/admin/newsletters">Publish newsletter</a></li>
+        {actions}
+        <li><a href="/admin/two-factor">Two-factor authentication</a></li>
+        <li><a href="/admin/sessions">Sessions</a></li>
+        <li><a href="/admin/api-tokens">API tokens</a></li>
         <form name="logoutForm" action="/admin/logout" method="post">
+        <input type="hidden" name="csrf_token" value="{csrf_token}">
         <input type="submit" value="Logout">
         </form>
         </li>
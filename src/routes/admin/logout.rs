@@ -1,15 +1,28 @@
 use crate::{
+    audit::{AuditAction, record_audit_event},
+    authentication::revoke_session,
     session_state::TypedSession,
     utils::{e500, see_other},
 };
-use actix_web::HttpResponse;
+use actix_web::{HttpRequest, HttpResponse, web};
 use actix_web_flash_messages::FlashMessage;
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
-    if session.get_user_id().map_err(e500)?.is_none() {
-        Ok(see_other("/login"))
-    } else {
-        session.log_out();
-        FlashMessage::info("You have successfully logged out.").send();
-        Ok(see_other("/login"))
+use sqlx::PgPool;
+
+pub async fn log_out(
+    session: TypedSession,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = session.get_user_id().map_err(e500)? else {
+        return Ok(see_other("/login"));
+    };
+    if let Some(session_id) = session.get_session_id().map_err(e500)? {
+        revoke_session(user_id, &session_id, &pool)
+            .await
+            .map_err(e500)?;
     }
+    record_audit_event(Some(user_id), AuditAction::Logout, None, &request, &pool).await;
+    session.log_out();
+    FlashMessage::info("You have successfully logged out.").send();
+    Ok(see_other("/login"))
 }
@@ -1,9 +1,21 @@
+mod api_tokens;
+mod audit;
 mod dashboard;
 mod logout;
 mod newsletter;
 mod password;
+mod sessions;
+mod subscribers;
+mod two_factor;
+mod users;
 
-pub use dashboard::admin_dashboard;
+pub use api_tokens::*;
+pub use audit::*;
+pub use dashboard::{admin_dashboard, get_username};
 pub use logout::*;
 pub use newsletter::*;
 pub use password::*;
+pub use sessions::*;
+pub use subscribers::*;
+pub use two_factor::*;
+pub use users::*;
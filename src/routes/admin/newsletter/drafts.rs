@@ -0,0 +1,97 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::request_id::current_request_id;
+
+/// A newsletter issue saved by an author, waiting for an editor to publish it.
+pub struct NewsletterDraft {
+    pub draft_id: Uuid,
+    pub title: String,
+    pub author_username: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The content of a draft being published.
+pub struct DraftContent {
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+#[tracing::instrument(
+    name = "Save a newsletter draft",
+    skip(html_content, text_content, pool)
+)]
+pub async fn insert_newsletter_draft(
+    author_id: Uuid,
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+    pool: &PgPool,
+) -> Result<Uuid, anyhow::Error> {
+    let draft_id = Uuid::new_v4();
+    let request_id = current_request_id();
+    sqlx::query!(
+        r#"
+INSERT INTO newsletter_drafts
+    (draft_id, title, html_content, text_content, author_id, created_at, request_id)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
+"#,
+        draft_id,
+        title,
+        html_content,
+        text_content,
+        author_id,
+        Utc::now(),
+        request_id.as_ref().map(|id| id.as_str()),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the newsletter draft.")?;
+    Ok(draft_id)
+}
+
+#[tracing::instrument(name = "List unpublished newsletter drafts", skip(pool))]
+pub async fn list_unpublished_drafts(pool: &PgPool) -> Result<Vec<NewsletterDraft>, anyhow::Error> {
+    sqlx::query_as!(
+        NewsletterDraft,
+        r#"
+SELECT d.draft_id, d.title, u.username AS "author_username?", d.created_at
+FROM newsletter_drafts d
+LEFT JOIN users u ON u.user_id = d.author_id
+WHERE d.published_at IS NULL
+ORDER BY d.created_at
+"#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve newsletter drafts.")
+}
+
+/// Mark a draft as published and return its content, or `None` if there is
+/// no such draft or it was already published. The row stays locked until
+/// `transaction` ends, so a draft is never delivered twice.
+#[tracing::instrument(name = "Claim a newsletter draft", skip(transaction))]
+pub async fn claim_draft_for_publishing(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    publisher_id: Uuid,
+) -> Result<Option<DraftContent>, anyhow::Error> {
+    sqlx::query_as!(
+        DraftContent,
+        r#"
+UPDATE newsletter_drafts
+SET published_at = $2, published_by = $3
+WHERE draft_id = $1 AND published_at IS NULL
+RETURNING title, html_content, text_content
+"#,
+        draft_id,
+        Utc::now(),
+        publisher_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to mark the newsletter draft as published.")
+}
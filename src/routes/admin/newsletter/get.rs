@@ -1,23 +1,60 @@
 use crate::{
+    authentication::{Permission, Role},
+    routes::admin::list_unpublished_drafts,
     session_state::TypedSession,
     utils::{e500, see_other},
 };
-use actix_web::{HttpResponse, http::header::ContentType};
-use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
 use std::fmt::Write;
 
 pub async fn newsletter_publish_form(
     session: TypedSession,
     flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut error_string = String::new();
-    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+    for m in flash_messages.iter() {
         writeln!(error_string, "<p><i>{}</i></p>", m.content()).unwrap()
     }
 
     if session.get_user_id().map_err(e500)?.is_none() {
         return Ok(see_other("/login"));
     };
+    let csrf_token = session.csrf_token().map_err(e500)?;
+    // Authors can only save drafts, editors also publish them
+    let can_publish = role.can(Permission::PublishNewsletters);
+    let mut draft_rows = String::new();
+    for draft in list_unpublished_drafts(&pool).await.map_err(e500)? {
+        let action = if can_publish {
+            format!(
+                r#"<form action="/admin/newsletters/drafts/{}/publish" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<button type="submit">Publish</button>
+</form>"#,
+                draft.draft_id
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            draft_rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&draft.title),
+            encode_minimal(draft.author_username.as_deref().unwrap_or("-")),
+            draft.created_at.format("%Y-%m-%d %H:%M UTC"),
+            action,
+        )
+        .unwrap()
+    }
+    let publish_button = if can_publish {
+        r#"<button type="submit">Publish Newsletter</button>"#
+    } else {
+        ""
+    };
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -30,6 +67,7 @@ pub async fn newsletter_publish_form(
 <body>
 {error_string}
 <form action="/admin/newsletters" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
 <label>Newsletter title
 <input
 type="text"
@@ -53,8 +91,14 @@ name="text_content"
 >
 </textarea>
 <br>
-<button type="submit">Publish Newsletter</button>
+<button type="submit" formaction="/admin/newsletters/drafts">Save draft</button>
+{publish_button}
 </form>
+<h2>Drafts awaiting publication</h2>
+<table>
+<tr><th>Title</th><th>Author</th><th>Saved</th><th>Actions</th></tr>
+{draft_rows}
+</table>
 <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>"#,
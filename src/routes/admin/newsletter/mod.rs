@@ -1,4 +1,6 @@
+mod drafts;
 mod get;
 mod post;
+pub use drafts::*;
 pub use get::*;
 pub use post::*;
@@ -1,11 +1,16 @@
 use actix_web::{HttpRequest, HttpResponse, web};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use sqlx::PgPool;
+use uuid::Uuid;
 
 use crate::{
+    audit::{AuditAction, record_audit_event},
     authentication::UserId,
     domain::SubscriberEmail,
     email_client::EmailClient,
+    error::AppError,
+    routes::admin::{claim_draft_for_publishing, insert_newsletter_draft},
     utils::{e500, see_other},
 };
 
@@ -38,27 +43,141 @@ pub async fn publish_newsletter(
     request: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id.to_string()));
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id.to_string()));
 
     if body.title.is_empty() {
         FlashMessage::error("Newsletter Publish Failed").send();
         return Ok(see_other("/admin/newsletters"));
     }
 
-    let confirmed_subscribers = get_confirmed_subscribers(&pool).await.map_err(e500)?;
+    deliver_newsletter(
+        &body.title,
+        &body.html_content,
+        &body.text_content,
+        &pool,
+        &email_client,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(
+        Some(*user_id),
+        AuditAction::NewsletterPublished,
+        Some(&body.title),
+        &request,
+        &pool,
+    )
+    .await;
+    FlashMessage::info("The newsletter issue has been published!").send();
+    Ok(see_other("/admin/newsletters"))
+}
+
+#[tracing::instrument(
+    name = "Save a newsletter draft",
+    skip(body, pool, request),
+    fields(email_title = %body.title, user_id = %*user_id)
+)]
+pub async fn save_newsletter_draft(
+    body: web::Form<NewsletterFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if body.title.is_empty() {
+        FlashMessage::error("The draft needs a title").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    insert_newsletter_draft(
+        **user_id,
+        &body.title,
+        &body.html_content,
+        &body.text_content,
+        &pool,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(
+        Some(**user_id),
+        AuditAction::NewsletterDrafted,
+        Some(&body.title),
+        &request,
+        &pool,
+    )
+    .await;
+    FlashMessage::info("The draft has been saved for an editor to publish.").send();
+    Ok(see_other("/admin/newsletters"))
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter draft",
+    skip(pool, email_client, request),
+    fields(user_id = %*user_id)
+)]
+pub async fn publish_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let draft = claim_draft_for_publishing(&mut transaction, *draft_id, **user_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| AppError::NotFound("No such unpublished draft.".into()))?;
+    // Left unpublished if delivery fails, as the transaction rolls back
+    deliver_newsletter(
+        &draft.title,
+        &draft.html_content,
+        &draft.text_content,
+        &pool,
+        &email_client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit database transaction.")
+        .map_err(e500)?;
+    record_audit_event(
+        Some(**user_id),
+        AuditAction::NewsletterPublished,
+        Some(&draft.title),
+        &request,
+        &pool,
+    )
+    .await;
+    FlashMessage::info("The newsletter issue has been published!").send();
+    Ok(see_other("/admin/newsletters"))
+}
+
+/// Email a newsletter issue to every confirmed subscriber.
+#[tracing::instrument(
+    name = "Deliver newsletter",
+    skip(html_content, text_content, pool, email_client)
+)]
+pub async fn deliver_newsletter(
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+    pool: &PgPool,
+    email_client: &EmailClient,
+) -> Result<(), anyhow::Error> {
+    let confirmed_subscribers = get_confirmed_subscribers(pool).await?;
 
     for subscriber in confirmed_subscribers {
         match subscriber {
             Ok(subscriber) => {
                 email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.html_content,
-                        &body.text_content,
-                    )
+                    .send_email(&subscriber.email, title, html_content, text_content)
                     .await
-                    .map_err(e500)?;
+                    .with_context(|| {
+                        format!("Failed to send newsletter issue to {}", subscriber.email)
+                    })?;
             }
             Err(error) => {
                 tracing::warn!(error.cause_chain  = ?error, "Skipping a confirmed subscriber. \
@@ -66,8 +185,7 @@ pub async fn publish_newsletter(
             }
         }
     }
-    FlashMessage::info("The newsletter issue has been published!").send();
-    Ok(see_other("/admin/newsletters"))
+    Ok(())
 }
 
 #[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
@@ -21,6 +21,7 @@ pub async fn change_password_form(
     if session.get_user_id().map_err(e500)?.is_none() {
         return Ok(see_other("/login"));
     };
+    let csrf_token = session.csrf_token().map_err(e500)?;
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -33,6 +34,7 @@ pub async fn change_password_form(
 <body>
 {error_string}
 <form action="/admin/password" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
 <label>Current password
 <input
 type="password"
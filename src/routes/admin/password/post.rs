@@ -1,11 +1,18 @@
-use actix_web::{HttpResponse, web};
+use actix_web::{HttpRequest, HttpResponse, web};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{AuthError, Credentials, UserId, validate_credentials},
+    audit::{AuditAction, record_audit_event},
+    authentication::{
+        AuthError, Credentials, UserId, check_password_policy, revoke_sessions,
+        validate_credentials,
+    },
+    background::BackgroundTasks,
+    configuration::{PasswordHashingSettings, PasswordPolicySettings},
     routes::admin::dashboard::get_username,
+    session_state::TypedSession,
     utils::{e500, see_other},
 };
 
@@ -15,9 +22,10 @@ pub struct FormData {
     new_password: Secret<String>,
     new_password_check: Secret<String>,
 }
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Change password submit",
-    skip(form, pool)
+    skip(form, pool, session, request, password_policy, hashing, background_tasks)
     fields(
         user_id=tracing::field::Empty,
     )
@@ -25,10 +33,15 @@ pub struct FormData {
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    session: TypedSession,
+    request: HttpRequest,
     user_id: web::ReqData<UserId>,
+    password_policy: web::Data<PasswordPolicySettings>,
+    hashing: web::Data<PasswordHashingSettings>,
+    background_tasks: web::Data<BackgroundTasks>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
 
     if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
         FlashMessage::error(
@@ -40,23 +53,50 @@ pub async fn change_password(
 
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
     let credentials = Credentials {
-        username,
-        password: form.0.current_password,
+        username: username.clone(),
+        password: form.current_password.clone(),
     };
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool, &background_tasks).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
                 Ok(see_other("/admin/password"))
             }
-            AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
 
-    // TODO check password length
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
+    let violations = check_password_policy(
+        &password_policy,
+        &form.new_password,
+        Some(&username),
+        Some(&form.current_password),
+    )
+    .await
+    .map_err(e500)?;
+    if !violations.is_empty() {
+        for violation in violations {
+            FlashMessage::error(violation.to_string()).send();
+        }
+        return Ok(see_other("/admin/password"));
+    }
+
+    crate::authentication::change_password(*user_id, form.0.new_password, &hashing, pool.get_ref())
         .await
         .map_err(e500)?;
+    // Whoever knew the old password is logged out everywhere else
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    revoke_sessions(*user_id, current_session_id.as_deref(), &pool)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        Some(*user_id),
+        AuditAction::PasswordChanged,
+        Some(&username),
+        &request,
+        &pool,
+    )
+    .await;
     FlashMessage::error("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
 }
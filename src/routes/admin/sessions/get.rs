@@ -0,0 +1,82 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::authentication::{UserId, list_sessions};
+use crate::configuration::SessionSettings;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+#[tracing::instrument(
+    name = "Get sessions list",
+    skip(session, flash_messages, pool, settings)
+)]
+pub async fn sessions_list(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    settings: web::Data<SessionSettings>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = *user_id.into_inner();
+    let csrf_token = session.csrf_token().map_err(e500)?;
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap()
+    }
+
+    let sessions = list_sessions(user_id, &settings, &pool)
+        .await
+        .map_err(e500)?;
+    let mut rows = String::new();
+    for active in sessions {
+        let actions = if current_session_id.as_deref() == Some(active.session_id.as_str()) {
+            "(this session)".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{id}/revoke" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<button type="submit">Revoke</button>
+</form>"#,
+                id = encode_minimal(&active.session_id),
+            )
+        };
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            active.created_at.format("%Y-%m-%d %H:%M UTC"),
+            active.last_seen_at.format("%Y-%m-%d %H:%M UTC"),
+            encode_minimal(active.ip_address.as_deref().unwrap_or("unknown")),
+            encode_minimal(active.user_agent.as_deref().unwrap_or("unknown")),
+            actions,
+        )
+        .unwrap()
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Sessions</title>
+</head>
+<body>
+{msg_html}
+<table>
+<tr><th>Signed in</th><th>Last seen</th><th>IP address</th><th>Browser</th><th>Actions</th></tr>
+{rows}
+</table>
+<form action="/admin/sessions/revoke-others" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<button type="submit">Log out all other sessions</button>
+</form>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
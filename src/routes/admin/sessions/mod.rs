@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::sessions_list;
+pub use post::*;
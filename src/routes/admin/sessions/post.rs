@@ -0,0 +1,65 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::audit::{AuditAction, record_audit_event};
+use crate::authentication::{UserId, revoke_session, revoke_sessions};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+
+#[tracing::instrument(name = "Revoke a session", skip(path, session, request, pool))]
+pub async fn revoke_one_session(
+    path: web::Path<String>,
+    session: TypedSession,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = *user_id.into_inner();
+    let session_id = path.into_inner();
+    if session.get_session_id().map_err(e500)?.as_deref() == Some(session_id.as_str()) {
+        FlashMessage::error("Use the logout button to end your current session.").send();
+        return Ok(see_other("/admin/sessions"));
+    }
+    if revoke_session(user_id, &session_id, &pool)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            Some(user_id),
+            AuditAction::SessionRevoked,
+            None,
+            &request,
+            &pool,
+        )
+        .await;
+        FlashMessage::info("The session has been revoked.").send();
+    } else {
+        FlashMessage::error("The session does not exist.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(name = "Revoke other sessions", skip(session, request, pool))]
+pub async fn revoke_other_sessions(
+    session: TypedSession,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = *user_id.into_inner();
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    revoke_sessions(user_id, current_session_id.as_deref(), &pool)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        Some(user_id),
+        AuditAction::OtherSessionsRevoked,
+        None,
+        &request,
+        &pool,
+    )
+    .await;
+    FlashMessage::info("All other sessions have been logged out.").send();
+    Ok(see_other("/admin/sessions"))
+}
@@ -0,0 +1,176 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::startup::ReadPool;
+use crate::utils::e500;
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+struct ConsentRow {
+    consented_at: DateTime<Utc>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    source: String,
+    consent_text_version: String,
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get subscribers list", skip(pool))]
+pub async fn subscribers_list(pool: web::Data<ReadPool>) -> Result<HttpResponse, actix_web::Error> {
+    let subscribers = get_subscribers(&pool).await.map_err(e500)?;
+    let mut rows = String::new();
+    for s in subscribers {
+        writeln!(
+            rows,
+            r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            s.id,
+            encode_minimal(&s.email),
+            encode_minimal(&s.name),
+            encode_minimal(&s.status),
+            s.subscribed_at.to_rfc3339(),
+        )
+        .unwrap()
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Subscribers</title>
+</head>
+<body>
+<table>
+<tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+{rows}
+</table>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get subscriber detail", skip(pool))]
+pub async fn subscriber_detail(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<ReadPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let Some(subscriber) = get_subscriber(subscriber_id, &pool).await.map_err(e500)? else {
+        return Err(AppError::NotFound("No such subscriber.".into()).into());
+    };
+    let consents = get_consents(subscriber_id, &pool).await.map_err(e500)?;
+    let mut consent_rows = String::new();
+    for c in consents {
+        writeln!(
+            consent_rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            c.consented_at.to_rfc3339(),
+            encode_minimal(c.ip_address.as_deref().unwrap_or("-")),
+            encode_minimal(c.user_agent.as_deref().unwrap_or("-")),
+            encode_minimal(&c.source),
+            encode_minimal(&c.consent_text_version),
+            c.confirmed_at
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(|| "-".into()),
+        )
+        .unwrap()
+    }
+    let email = encode_minimal(&subscriber.email);
+    let name = encode_minimal(&subscriber.name);
+    let status = encode_minimal(&subscriber.status);
+    let subscribed_at = subscriber.subscribed_at.to_rfc3339();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Subscriber</title>
+</head>
+<body>
+<p>Email: {email}</p>
+<p>Name: {name}</p>
+<p>Status: {status}</p>
+<p>Subscribed at: {subscribed_at}</p>
+<h2>Consent log</h2>
+<table>
+<tr><th>Consented at</th><th>IP address</th><th>User agent</th><th>Source</th><th>Consent text version</th><th>Confirmed at</th></tr>
+{consent_rows}
+</table>
+<p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(pool))]
+async fn get_subscribers(pool: &PgPool) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+ORDER BY subscribed_at DESC
+"#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscribers.")?;
+    Ok(rows)
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+async fn get_subscriber(
+    subscriber_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<SubscriberRow>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+WHERE id = $1
+"#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a subscriber.")?;
+    Ok(row)
+}
+
+#[tracing::instrument(name = "Get subscriber consents", skip(pool))]
+async fn get_consents(
+    subscriber_id: Uuid,
+    pool: &PgPool,
+) -> Result<Vec<ConsentRow>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        ConsentRow,
+        r#"
+SELECT consented_at, ip_address, user_agent, source, consent_text_version, confirmed_at
+FROM subscription_consents
+WHERE subscriber_id = $1
+ORDER BY consented_at DESC
+"#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscriber consents.")?;
+    Ok(rows)
+}
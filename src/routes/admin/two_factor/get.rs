@@ -0,0 +1,113 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::authentication::UserId;
+use crate::authentication::two_factor::{
+    generate_totp_secret, has_two_factor, provisioning_uri, qr_code_svg, remaining_recovery_codes,
+};
+use crate::configuration::TwoFactorSettings;
+use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+#[tracing::instrument(
+    name = "Get two-factor authentication form",
+    skip(session, flash_messages, pool, settings)
+)]
+pub async fn manage_two_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    settings: web::Data<TwoFactorSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap()
+    }
+    if session.two_factor_enrollment_required().map_err(e500)? {
+        writeln!(
+            msg_html,
+            "<p><i>You must set up two-factor authentication before using the admin area.</i></p>"
+        )
+        .unwrap()
+    }
+    let csrf_token = session.csrf_token().map_err(e500)?;
+
+    let content = if has_two_factor(*user_id, &pool).await.map_err(e500)? {
+        let remaining = remaining_recovery_codes(*user_id, &pool)
+            .await
+            .map_err(e500)?;
+        format!(
+            r#"<p>Two-factor authentication is enabled.</p>
+<p>You have {remaining} unused recovery codes left.</p>
+<form action="/admin/two-factor/disable" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<label>Authentication code
+<input
+type="text"
+placeholder="Enter a code to disable two-factor authentication"
+name="code"
+autocomplete="one-time-code"
+>
+</label>
+<br>
+<button type="submit">Disable two-factor authentication</button>
+</form>"#
+        )
+    } else {
+        let secret = match session.get_pending_totp_secret().map_err(e500)? {
+            Some(secret) => secret,
+            None => {
+                let secret = generate_totp_secret();
+                session.insert_pending_totp_secret(&secret).map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(*user_id, &pool).await.map_err(e500)?;
+        let uri = provisioning_uri(&settings, &username, &secret).map_err(e500)?;
+        let qr_code = qr_code_svg(&uri).map_err(e500)?;
+        let uri = encode_minimal(&uri);
+        let secret = secret.expose_secret();
+        format!(
+            r#"<p>Scan this QR code with your authenticator app:</p>
+{qr_code}
+<p>Or add it manually using the key <code>{secret}</code> or this <a href="{uri}">link</a>.</p>
+<form action="/admin/two-factor" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<label>Authentication code
+<input
+type="text"
+placeholder="Enter the code shown by your app"
+name="code"
+autocomplete="one-time-code"
+>
+</label>
+<br>
+<button type="submit">Enable two-factor authentication</button>
+</form>"#
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Two-factor authentication</title>
+</head>
+<body>
+{msg_html}
+{content}
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::manage_two_factor_form;
+pub use post::{enroll_two_factor, unenroll_two_factor};
@@ -0,0 +1,126 @@
+use actix_web::{HttpRequest, HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::audit::{AuditAction, record_audit_event};
+use crate::authentication::UserId;
+use crate::authentication::two_factor::{
+    check_totp_code, disable_two_factor, enable_two_factor, verify_second_factor,
+};
+use crate::configuration::TwoFactorSettings;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Enroll in two-factor authentication",
+    skip(form, session, request, pool, settings),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn enroll_two_factor(
+    form: web::Form<FormData>,
+    session: TypedSession,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    settings: web::Data<TwoFactorSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let Some(secret) = session.get_pending_totp_secret().map_err(e500)? else {
+        FlashMessage::error("Your enrollment expired, please scan the new QR code.").send();
+        return Ok(see_other("/admin/two-factor"));
+    };
+    let Some(step) =
+        check_totp_code(&settings, &secret, form.code.expose_secret()).map_err(e500)?
+    else {
+        FlashMessage::error("The authentication code is incorrect.").send();
+        return Ok(see_other("/admin/two-factor"));
+    };
+    let recovery_codes = enable_two_factor(*user_id, &secret, step, &pool, &settings)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        Some(*user_id),
+        AuditAction::TwoFactorEnabled,
+        None,
+        &request,
+        &pool,
+    )
+    .await;
+    session.remove_pending_totp_secret();
+    session
+        .set_two_factor_enrollment_required(false)
+        .map_err(e500)?;
+
+    let mut codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(codes_html, "<li><code>{}</code></li>", code).unwrap()
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Two-factor authentication</title>
+</head>
+<body>
+<p>Two-factor authentication is now enabled.</p>
+<p>Store these recovery codes somewhere safe. Each one can be used once to log in
+if you lose access to your authenticator app, and they will not be shown again.</p>
+<ul>
+{codes_html}
+</ul>
+<p><a href="/admin/dashboard">Continue to the dashboard</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(
+    name = "Disable two-factor authentication",
+    skip(form, session, request, pool, settings),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn unenroll_two_factor(
+    form: web::Form<FormData>,
+    session: TypedSession,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    settings: web::Data<TwoFactorSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let verified = verify_second_factor(*user_id, form.code.expose_secret(), &pool, &settings)
+        .await
+        .map_err(e500)?;
+    if !verified {
+        FlashMessage::error("The authentication code is incorrect.").send();
+        return Ok(see_other("/admin/two-factor"));
+    }
+    disable_two_factor(*user_id, &pool).await.map_err(e500)?;
+    record_audit_event(
+        Some(*user_id),
+        AuditAction::TwoFactorDisabled,
+        None,
+        &request,
+        &pool,
+    )
+    .await;
+    session
+        .set_two_factor_enrollment_required(settings.required)
+        .map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been disabled.").send();
+    Ok(see_other("/admin/two-factor"))
+}
@@ -0,0 +1,157 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::authentication::{Role, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+struct UserRow {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    is_active: bool,
+}
+
+#[tracing::instrument(name = "Get users list", skip(session, flash_messages, pool))]
+pub async fn users_list(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_user_id = *user_id.into_inner();
+    let csrf_token = session.csrf_token().map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap()
+    }
+
+    let users = get_users(&pool).await.map_err(e500)?;
+    let mut rows = String::new();
+    for user in users {
+        let status = if user.is_active {
+            "active"
+        } else {
+            "deactivated"
+        };
+        let email_form = format!(
+            r#"<form action="/admin/users/{id}/email" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<input type="email" placeholder="No email" name="email" value="{email}">
+<button type="submit">Save</button>
+</form>"#,
+            id = user.user_id,
+            email = encode_minimal(user.email.as_deref().unwrap_or_default()),
+        );
+        let actions = if user.user_id == current_user_id {
+            "(you)".to_string()
+        } else {
+            let (toggle_action, toggle_label) = if user.is_active {
+                ("deactivate", "Deactivate")
+            } else {
+                ("activate", "Activate")
+            };
+            format!(
+                r#"<form action="/admin/users/{id}/role" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<select name="role">{options}</select>
+<button type="submit">Change role</button>
+</form>
+<form action="/admin/users/{id}/{toggle_action}" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<button type="submit">{toggle_label}</button>
+</form>
+<form action="/admin/users/{id}/delete" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<button type="submit">Delete</button>
+</form>"#,
+                id = user.user_id,
+                options = role_options(Some(&user.role)),
+            )
+        };
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&user.username),
+            email_form,
+            encode_minimal(&user.role),
+            status,
+            actions,
+        )
+        .unwrap()
+    }
+    let options = role_options(None);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Users</title>
+</head>
+<body>
+{msg_html}
+<table>
+<tr><th>Username</th><th>Email</th><th>Role</th><th>Status</th><th>Actions</th></tr>
+{rows}
+</table>
+<h2>Invite a user</h2>
+<form action="/admin/users" method="post">
+<input type="hidden" name="csrf_token" value="{csrf_token}">
+<label>Username
+<input type="text" placeholder="Enter a username" name="username">
+</label>
+<label>Email
+<input type="email" placeholder="Used for password resets" name="email">
+</label>
+<label>Role
+<select name="role">{options}</select>
+</label>
+<button type="submit">Invite</button>
+</form>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+fn role_options(selected: Option<&str>) -> String {
+    let mut options = String::new();
+    for role in Role::ALL {
+        let role = role.as_str();
+        let selected = if selected == Some(role) {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            options,
+            r#"<option value="{role}"{selected}>{role}</option>"#
+        )
+        .unwrap()
+    }
+    options
+}
+
+#[tracing::instrument(name = "Get users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<UserRow>, anyhow::Error> {
+    sqlx::query_as!(
+        UserRow,
+        r#"
+SELECT user_id, username, email, role, is_active
+FROM users
+ORDER BY username
+"#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve users.")
+}
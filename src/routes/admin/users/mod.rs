@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::users_list;
+pub use post::*;
@@ -0,0 +1,361 @@
+use actix_web::{HttpRequest, HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use rand::distr::Alphanumeric;
+use rand::{Rng, rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+use crate::audit::{AuditAction, record_audit_event};
+use crate::authentication::{Role, UserId, create_user};
+use crate::configuration::PasswordHashingSettings;
+use crate::domain::SubscriberEmail;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    username: String,
+    #[serde(default)]
+    email: String,
+    role: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailFormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    role: String,
+}
+
+#[tracing::instrument(
+    name = "Invite a user",
+    skip(form, pool, hashing, current_user_id, request),
+    fields(username = %form.username)
+)]
+pub async fn invite_user(
+    form: web::Form<InviteFormData>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    current_user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let InviteFormData {
+        username,
+        email,
+        role,
+    } = form.0;
+    let username = username.trim();
+    if username.is_empty() || username.graphemes(true).count() > 256 {
+        FlashMessage::error("Usernames must be between 1 and 256 characters long.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let Ok(role) = Role::parse(&role) else {
+        FlashMessage::error("Please pick a valid role.").send();
+        return Ok(see_other("/admin/users"));
+    };
+    let email = match parse_optional_email(email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    if username_exists(username, &pool).await.map_err(e500)? {
+        FlashMessage::error("A user with that username already exists.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    if let Some(email) = &email
+        && email_in_use(email, None, &pool).await.map_err(e500)?
+    {
+        FlashMessage::error("Another user already uses that email address.").send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let password = Secret::new(
+        rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect::<String>(),
+    );
+    create_user(
+        username,
+        email.as_ref(),
+        password.clone(),
+        role,
+        &hashing,
+        &pool,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(
+        Some(**current_user_id),
+        AuditAction::UserInvited,
+        Some(username),
+        &request,
+        &pool,
+    )
+    .await;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>User invited</title>
+</head>
+<body>
+<p>{username} has been invited as {role}.</p>
+<p>Share this temporary password with them: <code>{password}</code></p>
+<p>It will not be shown again. Ask them to change it after logging in.</p>
+<p><a href="/admin/users">&lt;- Back</a></p>
+</body>
+</html>"#,
+            username = encode_minimal(username),
+            password = password.expose_secret(),
+        )))
+}
+
+#[tracing::instrument(
+    name = "Change a user's role",
+    skip(form, pool, current_user_id, request)
+)]
+pub async fn change_user_role(
+    form: web::Form<RoleFormData>,
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    current_user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = path.into_inner();
+    if user_id == **current_user_id {
+        FlashMessage::error("You cannot change your own role.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let Ok(role) = Role::parse(&form.role) else {
+        FlashMessage::error("Please pick a valid role.").send();
+        return Ok(see_other("/admin/users"));
+    };
+    let updated = sqlx::query!(
+        "UPDATE users SET role = $1 WHERE user_id = $2 RETURNING username",
+        role.as_str(),
+        user_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to update the user's role.")
+    .map_err(e500)?;
+    if let Some(updated) = updated {
+        record_audit_event(
+            Some(**current_user_id),
+            AuditAction::UserRoleChanged,
+            Some(&format!("{} ({})", updated.username, role)),
+            &request,
+            &pool,
+        )
+        .await;
+    }
+    FlashMessage::info("The user's role has been updated.").send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(
+    name = "Change a user's email",
+    skip(form, pool, current_user_id, request)
+)]
+pub async fn change_user_email(
+    form: web::Form<EmailFormData>,
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    current_user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = path.into_inner();
+    let email = match parse_optional_email(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    if let Some(email) = &email
+        && email_in_use(email, Some(user_id), &pool)
+            .await
+            .map_err(e500)?
+    {
+        FlashMessage::error("Another user already uses that email address.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let updated = sqlx::query!(
+        "UPDATE users SET email = lower($1) WHERE user_id = $2 RETURNING username",
+        email.as_ref().map(|email| email.as_ref()),
+        user_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to update the user's email.")
+    .map_err(e500)?;
+    if let Some(updated) = updated {
+        record_audit_event(
+            Some(**current_user_id),
+            AuditAction::UserEmailChanged,
+            Some(&updated.username),
+            &request,
+            &pool,
+        )
+        .await;
+    }
+    FlashMessage::info("The user's email has been updated.").send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Deactivate a user", skip(pool, current_user_id, request))]
+pub async fn deactivate_user(
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    current_user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_user_active(path.into_inner(), false, &pool, &current_user_id, &request).await
+}
+
+#[tracing::instrument(name = "Activate a user", skip(pool, current_user_id, request))]
+pub async fn activate_user(
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    current_user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_user_active(path.into_inner(), true, &pool, &current_user_id, &request).await
+}
+
+#[tracing::instrument(name = "Delete a user", skip(pool, current_user_id, request))]
+pub async fn delete_user(
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    current_user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = path.into_inner();
+    if user_id == **current_user_id {
+        FlashMessage::error("You cannot delete yourself.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let deleted = sqlx::query!(
+        "DELETE FROM users WHERE user_id = $1 RETURNING username",
+        user_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to delete the user.")
+    .map_err(e500)?;
+    if let Some(deleted) = deleted {
+        record_audit_event(
+            Some(**current_user_id),
+            AuditAction::UserDeleted,
+            Some(&deleted.username),
+            &request,
+            &pool,
+        )
+        .await;
+    }
+    FlashMessage::info("The user has been deleted.").send();
+    Ok(see_other("/admin/users"))
+}
+
+async fn set_user_active(
+    user_id: Uuid,
+    is_active: bool,
+    pool: &PgPool,
+    current_user_id: &UserId,
+    request: &HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if user_id == **current_user_id {
+        FlashMessage::error("You cannot change your own status.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let updated = sqlx::query!(
+        "UPDATE users SET is_active = $1 WHERE user_id = $2 RETURNING username",
+        is_active,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to update the user's status.")
+    .map_err(e500)?;
+    if let Some(updated) = updated {
+        let action = if is_active {
+            AuditAction::UserActivated
+        } else {
+            AuditAction::UserDeactivated
+        };
+        record_audit_event(
+            Some(**current_user_id),
+            action,
+            Some(&updated.username),
+            request,
+            pool,
+        )
+        .await;
+    }
+    let message = if is_active {
+        "The user has been activated."
+    } else {
+        "The user has been deactivated."
+    };
+    FlashMessage::info(message).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Check if a username is taken", skip(pool))]
+async fn username_exists(username: &str, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM users WHERE username = $1) AS "exists!""#,
+        username
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to check the username.")?;
+    Ok(row.exists)
+}
+
+/// An empty field means the user has no email address.
+fn parse_optional_email(email: String) -> Result<Option<SubscriberEmail>, String> {
+    let email = email.trim();
+    if email.is_empty() {
+        return Ok(None);
+    }
+    SubscriberEmail::parse(email.to_string())
+        .map(Some)
+        .map_err(|_| "Please enter a valid email address.".to_string())
+}
+
+#[tracing::instrument(name = "Check if an email is taken", skip(pool))]
+async fn email_in_use(
+    email: &SubscriberEmail,
+    except_user_id: Option<Uuid>,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+SELECT EXISTS(
+    SELECT 1 FROM users
+    WHERE lower(email) = lower($1) AND user_id IS DISTINCT FROM $2
+) AS "exists!"
+"#,
+        email.as_ref(),
+        except_user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to check the email.")?;
+    Ok(row.exists)
+}
@@ -0,0 +1,85 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use sqlx::PgPool;
+
+use crate::audit::{AuditAction, record_audit_event};
+use crate::authentication::UserId;
+use crate::email_client::EmailClient;
+use crate::error::AppError;
+use crate::routes::admin::{deliver_newsletter, insert_newsletter_draft};
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct NewsletterBody {
+    title: String,
+    html_content: String,
+    text_content: String,
+}
+
+#[tracing::instrument(
+    name = "Publish a new newsletter through the API",
+    skip(body, request, pool, email_client),
+    fields(email_title = %body.title, user_id = %*user_id)
+)]
+pub async fn api_publish_newsletter(
+    body: web::Json<NewsletterBody>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if body.title.trim().is_empty() {
+        return Err(AppError::BadRequest("The newsletter title must not be empty.".into()).into());
+    }
+    deliver_newsletter(
+        &body.title,
+        &body.html_content,
+        &body.text_content,
+        &pool,
+        &email_client,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(
+        Some(**user_id),
+        AuditAction::NewsletterPublished,
+        Some(&body.title),
+        &request,
+        &pool,
+    )
+    .await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "published" })))
+}
+
+#[tracing::instrument(
+    name = "Save a newsletter draft through the API",
+    skip(body, request, pool),
+    fields(email_title = %body.title, user_id = %*user_id)
+)]
+pub async fn api_save_newsletter_draft(
+    body: web::Json<NewsletterBody>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if body.title.trim().is_empty() {
+        return Err(AppError::BadRequest("The newsletter title must not be empty.".into()).into());
+    }
+    let draft_id = insert_newsletter_draft(
+        **user_id,
+        &body.title,
+        &body.html_content,
+        &body.text_content,
+        &pool,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(
+        Some(**user_id),
+        AuditAction::NewsletterDrafted,
+        Some(&body.title),
+        &request,
+        &pool,
+    )
+    .await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "drafted", "draft_id": draft_id })))
+}
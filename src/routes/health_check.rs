@@ -1,5 +1,187 @@
-use actix_web::{HttpResponse, Responder};
+use actix_web::{HttpResponse, Responder, web};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::configuration::HealthSettings;
+use crate::email_client::EmailClient;
+use crate::migrations::migration_status;
+use crate::rate_limit::RateLimiter;
+use crate::startup::ReadPool;
 
 pub async fn health_check() -> impl Responder {
     HttpResponse::Ok()
 }
+
+/// The process is up and able to serve requests. Dependencies are not
+/// checked, so an outage does not get every instance restarted.
+pub async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "live" }))
+}
+
+#[derive(serde::Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Up,
+    Down,
+}
+
+#[derive(serde::Serialize, Clone)]
+struct CheckResult {
+    status: CheckStatus,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+#[derive(serde::Serialize, Clone)]
+struct ReadinessReport {
+    status: &'static str,
+    checks: BTreeMap<&'static str, CheckResult>,
+}
+
+/// The last readiness report and when it was produced.
+#[derive(Default)]
+pub struct ReadinessCache(Mutex<Option<(Instant, ReadinessReport)>>);
+
+/// Whether every dependency needed to serve traffic answers.
+#[tracing::instrument(name = "Check readiness", skip_all)]
+pub async fn readiness(
+    pool: web::Data<PgPool>,
+    read_pool: web::Data<ReadPool>,
+    rate_limiter: web::Data<RateLimiter>,
+    email_client: web::Data<EmailClient>,
+    settings: web::Data<HealthSettings>,
+    cache: web::Data<ReadinessCache>,
+) -> HttpResponse {
+    // Holding the lock while checking makes concurrent probes share the result
+    let mut cached = cache.0.lock().await;
+    let report = match cached.as_ref() {
+        Some((checked_at, report)) if checked_at.elapsed() < settings.cache_ttl() => report.clone(),
+        _ => {
+            let report = check_dependencies(
+                &pool,
+                read_pool.replica(),
+                &rate_limiter,
+                &email_client,
+                &settings,
+            )
+            .await;
+            *cached = Some((Instant::now(), report.clone()));
+            report
+        }
+    };
+    drop(cached);
+
+    if report.status == "ready" {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+async fn check_dependencies(
+    pool: &PgPool,
+    replica: Option<&PgPool>,
+    rate_limiter: &RateLimiter,
+    email_client: &EmailClient,
+    settings: &HealthSettings,
+) -> ReadinessReport {
+    let timeout = settings.timeout();
+    let (postgres, replica, redis, migrations, email_provider) = tokio::join!(
+        check(timeout, ping_postgres(pool)),
+        async {
+            match replica {
+                Some(replica) => Some(check(timeout, ping_postgres(replica)).await),
+                None => None,
+            }
+        },
+        check(timeout, async {
+            rate_limiter.ping().await.context("Failed to ping Redis")
+        }),
+        check(timeout, check_migrations(pool)),
+        async {
+            if !settings.check_email_provider {
+                return None;
+            }
+            Some(
+                check(timeout, async {
+                    email_client
+                        .ping()
+                        .await
+                        .context("Failed to reach the email provider")
+                })
+                .await,
+            )
+        },
+    );
+
+    let mut checks = BTreeMap::from([
+        ("postgres", postgres),
+        ("redis", redis),
+        ("migrations", migrations),
+    ]);
+    if let Some(replica) = replica {
+        checks.insert("postgres_replica", replica);
+    }
+    if let Some(email_provider) = email_provider {
+        checks.insert("email_provider", email_provider);
+    }
+    let ready = checks.values().all(|check| check.status == CheckStatus::Up);
+    ReadinessReport {
+        status: if ready { "ready" } else { "unavailable" },
+        checks,
+    }
+}
+
+async fn ping_postgres(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .context("Failed to query Postgres")?;
+    Ok(())
+}
+
+/// Every migration this binary ships with has been applied.
+async fn check_migrations(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let pending = migration_status(&mut connection).await?.pending.len();
+    if pending > 0 {
+        anyhow::bail!("{} pending migration(s)", pending);
+    }
+    Ok(())
+}
+
+/// Time `probe`, treating errors and timeouts as the dependency being down.
+async fn check(
+    timeout: Duration,
+    probe: impl Future<Output = Result<(), anyhow::Error>>,
+) -> CheckResult {
+    let start = Instant::now();
+    let outcome = match tokio::time::timeout(timeout, probe).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(anyhow::anyhow!("Timed out")),
+    };
+    let latency_ms = start.elapsed().as_millis();
+    match outcome {
+        Ok(()) => CheckResult {
+            status: CheckStatus::Up,
+            latency_ms,
+            detail: None,
+        },
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, "A readiness check failed.");
+            CheckResult {
+                status: CheckStatus::Down,
+                latency_ms,
+                detail: Some(e.to_string()),
+            }
+        }
+    }
+}
@@ -1,7 +1,15 @@
-use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use chrono::Utc;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+use crate::routes::{CONSENT_TEXT, CONSENT_TEXT_VERSION, sign_form_timestamp};
+use crate::startup::HmacSecret;
+
+pub async fn home(hmac_secret: web::Data<HmacSecret>) -> HttpResponse {
+    let form_token = sign_form_timestamp(&hmac_secret.0, Utc::now());
+    HttpResponse::Ok().content_type(ContentType::html()).body(
+        include_str!("home.html")
+            .replace("{form_token}", &form_token)
+            .replace("{consent_text}", CONSENT_TEXT)
+            .replace("{consent_text_version}", CONSENT_TEXT_VERSION),
+    )
 }
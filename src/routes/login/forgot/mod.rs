@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::forgot_password_form;
+pub use post::request_password_reset;
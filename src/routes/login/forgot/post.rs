@@ -0,0 +1,147 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::create_password_reset_token;
+use crate::configuration::PasswordResetSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::rate_limit::{RateLimitDecision, RateLimiter};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{client_ip, e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, request, pool, email_client, base_url, rate_limiter, settings),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn request_password_reset(
+    form: web::Form<FormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    rate_limiter: web::Data<RateLimiter>,
+    settings: web::Data<PasswordResetSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // The same answer is given whether or not the address belongs to a user,
+    // so the form cannot be used to enumerate accounts.
+    let done = || {
+        FlashMessage::info(
+            "If an account with that email address exists, we have sent it a password reset link.",
+        )
+        .send();
+        Ok(see_other("/login/forgot"))
+    };
+
+    let ip_key = format!("password_reset:ip:{}", client_ip(&request));
+    if is_rate_limited(
+        &rate_limiter,
+        &ip_key,
+        settings.max_requests_per_ip,
+        &settings,
+    )
+    .await
+    .map_err(e500)?
+    {
+        FlashMessage::error("Too many password reset requests. Please try again later.").send();
+        return Ok(see_other("/login/forgot"));
+    }
+    let Ok(email) = SubscriberEmail::parse(form.0.email.trim().to_lowercase()) else {
+        return done();
+    };
+    let email_key = format!(
+        "password_reset:email:{}",
+        hex::encode(Sha256::digest(email.as_ref().as_bytes()))
+    );
+    if is_rate_limited(
+        &rate_limiter,
+        &email_key,
+        settings.max_requests_per_email,
+        &settings,
+    )
+    .await
+    .map_err(e500)?
+    {
+        return done();
+    }
+
+    let Some(user_id) = get_user_id_by_email(&email, &pool).await.map_err(e500)? else {
+        return done();
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let token = create_password_reset_token(user_id, settings.token_ttl(), &pool)
+        .await
+        .map_err(e500)?;
+    send_password_reset_email(&email_client, &email, &base_url.0, &token)
+        .await
+        .context("Failed to send the password reset email.")
+        .map_err(e500)?;
+    done()
+}
+
+async fn is_rate_limited(
+    rate_limiter: &RateLimiter,
+    key: &str,
+    max_attempts: u64,
+    settings: &PasswordResetSettings,
+) -> Result<bool, anyhow::Error> {
+    let decision = rate_limiter
+        .hit(key, max_attempts, settings.rate_limit_window())
+        .await
+        .context("Failed to check the password reset rate limit.")?;
+    if let RateLimitDecision::Limited { .. } = decision {
+        tracing::warn!(rate_limit_key = %key, "Password reset rate limit exceeded.");
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+#[tracing::instrument(name = "Get user by email", skip(email, pool))]
+async fn get_user_id_by_email(
+    email: &SubscriberEmail,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+SELECT user_id
+FROM users
+WHERE lower(email) = lower($1) AND is_active
+"#,
+        email.as_ref(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a user by email.")?;
+    Ok(row.map(|row| row.user_id))
+}
+
+async fn send_password_reset_email(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let reset_link = format!("{}/login/reset?token={}", base_url, token);
+    let html_body = format!(
+        r#"Someone asked to reset the password of your account.<br />
+    Click <a href="{}">here</a> to choose a new password.<br />
+    If it was not you, you can safely ignore this email."#,
+        reset_link
+    );
+    let plain_body = format!(
+        "Someone asked to reset the password of your account.\nVisit {} to choose a new password.\nIf it was not you, you can safely ignore this email.",
+        reset_link
+    );
+    email_client
+        .send_email(recipient, "Reset your password", &html_body, &plain_body)
+        .await
+}
@@ -1,12 +1,25 @@
-use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web::{HttpResponse, http::header::ContentType, web};
 use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
 use std::fmt::Write;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+use crate::oidc_client::OidcClient;
+
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    oidc_client: web::Data<Option<OidcClient>>,
+) -> HttpResponse {
     let mut error_string = String::new();
     for m in flash_messages.iter() {
         writeln!(error_string, "<p><i>{}</i></p>", m.content()).unwrap()
     }
+    let single_sign_on = match oidc_client.as_ref() {
+        Some(oidc_client) => format!(
+            r#"<p><a href="/login/oidc">Log in with {}</a></p>"#,
+            encode_minimal(oidc_client.display_name())
+        ),
+        None => String::new(),
+    };
 
     HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -34,8 +47,14 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
     name="password"
     >
     </label>
+    <label>
+    <input type="checkbox" name="remember_me" value="on">
+    Remember me
+    </label>
     <button type="submit">Login</button>
     </form>
+    {single_sign_on}
+    <p><a href="/login/forgot">Forgot your password?</a></p>
     </body>
     </html>"#,
         ))
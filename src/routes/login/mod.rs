@@ -1,4 +1,13 @@
+mod forgot;
 mod get;
+mod oidc;
 mod post;
+mod reset;
+mod throttle;
+mod two_factor;
+pub use forgot::*;
 pub use get::login_form;
+pub use oidc::*;
 pub use post::login;
+pub use reset::*;
+pub use two_factor::*;
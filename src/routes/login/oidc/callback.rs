@@ -0,0 +1,161 @@
+use actix_web::error::InternalError;
+use actix_web::{HttpRequest, HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::FlashMessage;
+use chrono::Utc;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use super::{PENDING_LOGIN_TTL_SECONDS, get_pending_login, removal_cookie};
+use crate::authentication::find_user_for_identity;
+use crate::authentication::two_factor::has_two_factor;
+use crate::configuration::{SessionSettings, TwoFactorSettings};
+use crate::oidc_client::OidcClient;
+use crate::rate_limit::RateLimiter;
+use crate::routes::admin::get_username;
+use crate::routes::login::post::{LoginError, complete_login};
+use crate::routes::login::throttle::ThrottleKeys;
+use crate::session_state::{PendingLogin, TypedSession};
+use crate::startup::HmacSecret;
+use crate::utils::{client_ip, constant_time_eq, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct CallbackParameters {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Finish an OIDC login once the provider sends the user back.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Complete OIDC login",
+    skip_all,
+    fields(user_id=tracing::field::Empty, ip=tracing::field::Empty)
+)]
+pub async fn oidc_callback(
+    parameters: web::Query<CallbackParameters>,
+    request: HttpRequest,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    oidc_client: web::Data<Option<OidcClient>>,
+    hmac_secret: web::Data<HmacSecret>,
+    rate_limiter: web::Data<RateLimiter>,
+    session_settings: web::Data<SessionSettings>,
+    two_factor_settings: web::Data<TwoFactorSettings>,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let Some(oidc_client) = oidc_client.as_ref() else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let ip = client_ip(&request);
+    tracing::Span::current().record("ip", tracing::field::display(&ip));
+
+    let pending = get_pending_login(&request, &hmac_secret)
+        .filter(|pending| Utc::now().timestamp() - pending.started_at <= PENDING_LOGIN_TTL_SECONDS)
+        .ok_or_else(|| fail(LoginError::ExpiredLoginAttempt))?;
+    let state_matches = parameters
+        .state
+        .as_deref()
+        .is_some_and(|state| constant_time_eq(state, &pending.state));
+    if !state_matches {
+        tracing::warn!("Rejecting an OIDC callback with a mismatched state.");
+        return Err(fail(LoginError::ExpiredLoginAttempt));
+    }
+    if let Some(error) = &parameters.error {
+        let e = anyhow::anyhow!("The OIDC provider returned an error: {error}");
+        return Err(fail(LoginError::SingleSignOnFailed(e)));
+    }
+    let Some(code) = parameters.code.as_deref() else {
+        let e = anyhow::anyhow!("The OIDC callback has no authorization code");
+        return Err(fail(LoginError::SingleSignOnFailed(e)));
+    };
+
+    let metadata = oidc_client
+        .discover()
+        .await
+        .map_err(|e| fail(LoginError::SingleSignOnFailed(e)))?;
+    let claims = oidc_client
+        .exchange_code(
+            &metadata,
+            code,
+            &Secret::new(pending.code_verifier),
+            &pending.nonce,
+        )
+        .await
+        .map_err(|e| fail(LoginError::SingleSignOnFailed(e)))?;
+    let user_id = find_user_for_identity(
+        oidc_client.issuer(),
+        &claims,
+        oidc_client.link_by_email(),
+        &pool,
+    )
+    .await
+    .map_err(|e| fail(LoginError::UnexpectedError(e)))?
+    .ok_or_else(|| fail(LoginError::NoLinkedAccount))?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let username = get_username(user_id, &pool)
+        .await
+        .map_err(|e| fail(LoginError::UnexpectedError(e)))?;
+    let trust_provider_mfa = oidc_client.trust_provider_mfa();
+    if !trust_provider_mfa
+        && has_two_factor(user_id, &pool)
+            .await
+            .map_err(|e| fail(LoginError::UnexpectedError(e)))?
+    {
+        session.renew();
+        session
+            .insert_pending_login(&PendingLogin {
+                user_id,
+                username,
+                started_at: Utc::now().timestamp(),
+                remember_me: false,
+            })
+            .map_err(|e| fail(LoginError::UnexpectedError(e.into())))?;
+        return Ok(continue_to("/login/two-factor"));
+    }
+    complete_login(
+        &session,
+        &request,
+        &pool,
+        user_id,
+        trust_provider_mfa,
+        false,
+        &rate_limiter,
+        &ThrottleKeys::new(&username, &ip),
+        &session_settings,
+        &two_factor_settings,
+    )
+    .await
+    .map_err(fail)?;
+    Ok(continue_to("/admin/dashboard"))
+}
+
+/// Send the browser on to `location`. A redirect would still count as
+/// cross-site, so the strict session cookie would not be sent along with it.
+fn continue_to(location: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(removal_cookie())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<meta http-equiv="refresh" content="0; url={location}">
+<title>Logging in</title>
+</head>
+<body>
+<p><a href="{location}">Continue</a></p>
+</body>
+</html>"#
+        ))
+}
+
+fn fail(e: LoginError) -> InternalError<LoginError> {
+    FlashMessage::error(e.to_string()).send();
+    let mut response = see_other("/login");
+    response
+        .add_cookie(&removal_cookie())
+        .expect("The removal cookie is a valid header value");
+    InternalError::from_response(e, response)
+}
@@ -0,0 +1,69 @@
+mod callback;
+mod start;
+pub use callback::oidc_callback;
+pub use start::oidc_login;
+
+use actix_web::HttpRequest;
+use actix_web::cookie::{Cookie, CookieJar, Key, SameSite, time::Duration};
+use secrecy::ExposeSecret;
+
+use crate::configuration::SessionSettings;
+use crate::startup::HmacSecret;
+
+/// How long the user has to authenticate with the provider.
+const PENDING_LOGIN_TTL_SECONDS: i64 = 10 * 60;
+const PENDING_LOGIN_COOKIE: &str = "oidc_login";
+
+/// What the callback needs to check the provider's answer against.
+///
+/// Kept in its own encrypted, `SameSite=Lax` cookie rather than in the session:
+/// the provider redirects back cross-site, which a strict session cookie would
+/// not survive.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PendingOidcLogin {
+    state: String,
+    nonce: String,
+    code_verifier: String,
+    /// Unix timestamp of the redirect to the provider.
+    started_at: i64,
+}
+
+fn cookie_key(hmac_secret: &HmacSecret) -> Key {
+    Key::from(hmac_secret.0.expose_secret().as_bytes())
+}
+
+fn pending_login_cookie(
+    pending: &PendingOidcLogin,
+    hmac_secret: &HmacSecret,
+    session_settings: &SessionSettings,
+) -> Result<Cookie<'static>, anyhow::Error> {
+    let mut cookie = Cookie::new(PENDING_LOGIN_COOKIE, serde_json::to_string(pending)?);
+    cookie.set_path("/login/oidc");
+    cookie.set_http_only(true);
+    cookie.set_secure(session_settings.cookie_secure);
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_max_age(Duration::seconds(PENDING_LOGIN_TTL_SECONDS));
+    let mut jar = CookieJar::new();
+    jar.private_mut(&cookie_key(hmac_secret)).add(cookie);
+    jar.delta()
+        .next()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Failed to encrypt the OIDC login cookie."))
+}
+
+fn get_pending_login(request: &HttpRequest, hmac_secret: &HmacSecret) -> Option<PendingOidcLogin> {
+    let cookie = request.cookie(PENDING_LOGIN_COOKIE)?;
+    let mut jar = CookieJar::new();
+    jar.add_original(cookie);
+    let cookie = jar
+        .private(&cookie_key(hmac_secret))
+        .get(PENDING_LOGIN_COOKIE)?;
+    serde_json::from_str(cookie.value()).ok()
+}
+
+fn removal_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::new(PENDING_LOGIN_COOKIE, "");
+    cookie.set_path("/login/oidc");
+    cookie.make_removal();
+    cookie
+}
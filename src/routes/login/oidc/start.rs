@@ -0,0 +1,55 @@
+use actix_web::error::InternalError;
+use actix_web::{HttpResponse, http::header::LOCATION, web};
+use chrono::Utc;
+use rand::{Rng, distr::Alphanumeric, rng};
+use secrecy::Secret;
+
+use super::{PendingOidcLogin, pending_login_cookie};
+use crate::configuration::SessionSettings;
+use crate::oidc_client::OidcClient;
+use crate::routes::login::post::{LoginError, login_redirect};
+use crate::startup::HmacSecret;
+
+/// Send the user to the OIDC provider to authenticate.
+#[tracing::instrument(name = "Start OIDC login", skip_all)]
+pub async fn oidc_login(
+    oidc_client: web::Data<Option<OidcClient>>,
+    hmac_secret: web::Data<HmacSecret>,
+    session_settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let Some(oidc_client) = oidc_client.as_ref() else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let metadata = oidc_client
+        .discover()
+        .await
+        .map_err(|e| login_redirect(LoginError::SingleSignOnFailed(e)))?;
+
+    let pending = PendingOidcLogin {
+        state: random_string(32),
+        nonce: random_string(32),
+        code_verifier: random_string(64),
+        started_at: Utc::now().timestamp(),
+    };
+    let authorization_url = oidc_client
+        .authorization_url(
+            &metadata,
+            &pending.state,
+            &pending.nonce,
+            &Secret::new(pending.code_verifier.clone()),
+        )
+        .map_err(|e| login_redirect(LoginError::SingleSignOnFailed(e)))?;
+    let cookie = pending_login_cookie(&pending, &hmac_secret, &session_settings)
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, authorization_url))
+        .cookie(cookie)
+        .finish())
+}
+
+fn random_string(length: usize) -> String {
+    let mut rng = rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric) as char)
+        .take(length)
+        .collect()
+}
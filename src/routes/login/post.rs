@@ -1,69 +1,233 @@
 use actix_web::error::InternalError;
-use actix_web::{HttpResponse, http::header::LOCATION, web};
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse,
+    http::header::{LOCATION, USER_AGENT},
+    web,
+};
 use actix_web_flash_messages::FlashMessage;
+use chrono::Utc;
 use secrecy::Secret;
 use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
 
-use crate::session_state::TypedSession;
+use super::throttle::{ThrottleKeys, locked_for, record_failure};
+use crate::audit::{AuditAction, record_audit_event};
+use crate::authentication::two_factor::has_two_factor;
+use crate::background::BackgroundTasks;
+use crate::configuration::{
+    LoginSettings, PasswordHashingSettings, SessionSettings, TwoFactorSettings,
+};
+use crate::rate_limit::RateLimiter;
+use crate::session_state::{PendingLogin, TypedSession};
 use crate::{
-    authentication::{AuthError, Credentials, validate_credentials},
-    utils::error_chain_fmt,
+    authentication::{
+        AuthError, Credentials, RememberedSession, register_session, validate_credentials,
+    },
+    utils::{client_ip, error_chain_fmt},
 };
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     username: String,
     password: Secret<String>,
+    /// Checkboxes are only submitted when ticked.
+    #[serde(default)]
+    remember_me: Option<String>,
 }
 
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
-skip(form, pool, session),
-fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+skip(form, pool, session, request, rate_limiter, settings, session_settings, two_factor_settings, hashing, background_tasks),
+fields(username=tracing::field::Empty, user_id=tracing::field::Empty, ip=tracing::field::Empty)
 )]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    request: HttpRequest,
+    rate_limiter: web::Data<RateLimiter>,
+    settings: web::Data<LoginSettings>,
+    session_settings: web::Data<SessionSettings>,
+    two_factor_settings: web::Data<TwoFactorSettings>,
+    hashing: web::Data<PasswordHashingSettings>,
+    background_tasks: web::Data<BackgroundTasks>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let remember_me = form.remember_me.is_some();
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
-    tracing::Span::current().record("user_id", tracing::field::display(&credentials.username));
+    let ip = client_ip(&request);
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    tracing::Span::current().record("ip", tracing::field::display(&ip));
+
+    let username = credentials.username.clone();
+    let throttle_keys = ThrottleKeys::new(&username, &ip);
+    if let Some(retry_after) = locked_for(&rate_limiter, &throttle_keys)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+    {
+        tracing::warn!("Rejecting a login attempt for a locked out username or IP.");
+        return Err(login_redirect(LoginError::LockedOut { retry_after }));
+    }
 
-    match validate_credentials(credentials, &pool).await {
+    match validate_credentials(credentials, &hashing, &pool, &background_tasks).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-            session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
-            Ok(HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/admin/dashboard"))
-                .finish())
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            let two_factor = has_two_factor(user_id, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            if two_factor {
+                session.renew();
+                session
+                    .insert_pending_login(&PendingLogin {
+                        user_id,
+                        username,
+                        started_at: Utc::now().timestamp(),
+                        remember_me,
+                    })
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((LOCATION, "/login/two-factor"))
+                    .finish());
+            }
+            complete_login(
+                &session,
+                &request,
+                &pool,
+                user_id,
+                false,
+                remember_me,
+                &rate_limiter,
+                &throttle_keys,
+                &session_settings,
+                &two_factor_settings,
+            )
+            .await
+            .map_err(login_redirect)
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    record_audit_event(
+                        None,
+                        AuditAction::LoginFailed,
+                        Some(&username),
+                        &request,
+                        &pool,
+                    )
+                    .await;
+                    match record_failure(&rate_limiter, &settings, &throttle_keys).await {
+                        Ok(Some(retry_after)) => LoginError::LockedOut { retry_after },
+                        Ok(None) => LoginError::AuthError(e.into()),
+                        Err(e) => LoginError::UnexpectedError(e),
+                    }
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
-            Err(login_redirect(e.into()))
+            Err(login_redirect(e))
         }
     }
 }
 
-fn login_redirect(e: LoginError) -> InternalError<LoginError> {
+/// Attach `user_id` to a fresh, registered session once every required factor
+/// checked out.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn complete_login(
+    session: &TypedSession,
+    request: &HttpRequest,
+    pool: &PgPool,
+    user_id: Uuid,
+    has_two_factor: bool,
+    remember_me: bool,
+    rate_limiter: &RateLimiter,
+    throttle_keys: &ThrottleKeys,
+    session_settings: &SessionSettings,
+    two_factor_settings: &TwoFactorSettings,
+) -> Result<HttpResponse, LoginError> {
+    rate_limiter
+        .reset(&throttle_keys.username_failures)
+        .await
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+    let session_id = register_session(
+        user_id,
+        &client_ip(request),
+        user_agent,
+        remember_me,
+        session_settings,
+        pool,
+    )
+    .await
+    .map_err(LoginError::UnexpectedError)?;
+    if remember_me {
+        request.extensions_mut().insert(RememberedSession);
+    }
+    record_audit_event(
+        Some(user_id),
+        AuditAction::LoginSucceeded,
+        None,
+        request,
+        pool,
+    )
+    .await;
+    session.renew();
+    session
+        .insert_user_id(user_id)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    session
+        .insert_session_id(&session_id)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    session
+        .set_two_factor_enrollment_required(two_factor_settings.required && !has_two_factor)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/dashboard"))
+        .finish())
+}
+
+pub(super) fn login_redirect(e: LoginError) -> InternalError<LoginError> {
+    redirect_with_error(e, "/login")
+}
+
+pub(super) fn redirect_with_error(e: LoginError, location: &str) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
     let response = HttpResponse::SeeOther()
-        .insert_header((LOCATION, "/login"))
+        .insert_header((LOCATION, location))
         .finish();
     InternalError::from_response(e, response)
 }
 
+fn format_retry_after(retry_after: &Duration) -> String {
+    let minutes = retry_after.as_secs().div_ceil(60);
+    if minutes <= 1 {
+        "1 minute".into()
+    } else {
+        format!("{} minutes", minutes)
+    }
+}
+
 #[derive(thiserror::Error)]
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("Invalid authentication code.")]
+    InvalidSecondFactor,
+    #[error("Your login attempt expired, please log in again.")]
+    ExpiredLoginAttempt,
+    #[error("Single sign-on failed, please try again.")]
+    SingleSignOnFailed(#[source] anyhow::Error),
+    #[error("No active account is linked to this identity. Ask an owner to invite you.")]
+    NoLinkedAccount,
+    #[error(
+        "Too many failed login attempts. Try again in {}.",
+        format_retry_after(.retry_after)
+    )]
+    LockedOut { retry_after: Duration },
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -0,0 +1,65 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::authentication::get_password_reset_token_user;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+pub async fn reset_password_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap()
+    }
+    let token = &parameters.token;
+    let content = if get_password_reset_token_user(token, &pool)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        format!(
+            r#"<form action="/login/reset" method="post">
+    <input type="hidden" name="token" value="{}">
+    <label>New password
+    <input type="password" placeholder="Enter new password" name="new_password">
+    </label>
+    <br>
+    <label>Confirm new password
+    <input type="password" placeholder="Type the new password again" name="new_password_check">
+    </label>
+    <br>
+    <button type="submit">Reset password</button>
+    </form>"#,
+            htmlescape::encode_attribute(token)
+        )
+    } else {
+        r#"<p>This password reset link is invalid or has expired.</p>
+    <p><a href="/login/forgot">Request a new one</a></p>"#
+            .to_string()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+    <head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Reset password</title>
+    </head>
+    <body>
+    {msg_html}
+    {content}
+    </body>
+    </html>"#,
+        )))
+}
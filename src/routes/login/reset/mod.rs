@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::reset_password_form;
+pub use post::reset_password;
@@ -0,0 +1,100 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::audit::{AuditAction, record_audit_event};
+use crate::authentication::{
+    change_password, check_password_policy, consume_password_reset_token,
+    get_password_reset_token_user, revoke_sessions,
+};
+use crate::configuration::{PasswordHashingSettings, PasswordPolicySettings};
+use crate::routes::admin::get_username;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Reset password",
+    skip(form, request, pool, password_policy, hashing),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn reset_password(
+    form: web::Form<FormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    password_policy: web::Data<PasswordPolicySettings>,
+    hashing: web::Data<PasswordHashingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let retry = format!("/login/reset?token={}", urlencoding::encode(&form.token));
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match.",
+        )
+        .send();
+        return Ok(see_other(&retry));
+    }
+    let Some(user_id) = get_password_reset_token_user(&form.token, &pool)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(expired_link());
+    };
+    let username = get_username(user_id, &pool).await.map_err(e500)?;
+    let violations =
+        check_password_policy(&password_policy, &form.new_password, Some(&username), None)
+            .await
+            .map_err(e500)?;
+    if !violations.is_empty() {
+        for violation in violations {
+            FlashMessage::error(violation.to_string()).send();
+        }
+        return Ok(see_other(&retry));
+    }
+
+    // The link stays usable unless the new password is saved too
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    if consume_password_reset_token(&form.token, &mut transaction)
+        .await
+        .map_err(e500)?
+        != Some(user_id)
+    {
+        return Ok(expired_link());
+    }
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    change_password(user_id, form.0.new_password, &hashing, &mut *transaction)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password reset.")
+        .map_err(e500)?;
+    revoke_sessions(user_id, None, &pool).await.map_err(e500)?;
+    record_audit_event(
+        Some(user_id),
+        AuditAction::PasswordReset,
+        Some(&username),
+        &request,
+        &pool,
+    )
+    .await;
+
+    FlashMessage::info("Your password has been reset, you can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+fn expired_link() -> HttpResponse {
+    FlashMessage::error("This password reset link is invalid or has expired.").send();
+    see_other("/login/forgot")
+}
@@ -0,0 +1,112 @@
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+use crate::configuration::LoginSettings;
+use crate::rate_limit::{RateLimitDecision, RateLimiter};
+
+/// Redis keys tracking failed attempts and lockouts for a login attempt.
+pub(super) struct ThrottleKeys {
+    pub(super) username_failures: String,
+    username_lock: String,
+    ip_failures: String,
+    ip_lock: String,
+}
+
+impl ThrottleKeys {
+    pub(super) fn new(username: &str, ip: &str) -> Self {
+        // Usernames are attacker controlled: hash them to bound the key length.
+        let username = hex::encode(Sha256::digest(username.as_bytes()));
+        Self {
+            username_failures: format!("login:failures:username:{}", username),
+            username_lock: format!("login:lock:username:{}", username),
+            ip_failures: format!("login:failures:ip:{}", ip),
+            ip_lock: format!("login:lock:ip:{}", ip),
+        }
+    }
+}
+
+pub(super) async fn locked_for(
+    rate_limiter: &RateLimiter,
+    keys: &ThrottleKeys,
+) -> Result<Option<Duration>, anyhow::Error> {
+    let username = rate_limiter
+        .locked_for(&keys.username_lock)
+        .await
+        .context("Failed to check the username lockout.")?;
+    let ip = rate_limiter
+        .locked_for(&keys.ip_lock)
+        .await
+        .context("Failed to check the IP lockout.")?;
+    Ok(username.max(ip))
+}
+
+/// Count a failed attempt, locking out the username or IP when it crosses its
+/// threshold and otherwise stalling the response progressively longer.
+#[tracing::instrument(name = "Record failed login attempt", skip_all)]
+pub(super) async fn record_failure(
+    rate_limiter: &RateLimiter,
+    settings: &LoginSettings,
+    keys: &ThrottleKeys,
+) -> Result<Option<Duration>, anyhow::Error> {
+    let username = rate_limiter
+        .hit(
+            &keys.username_failures,
+            settings.max_failures_per_username.saturating_sub(1),
+            settings.failure_window(),
+        )
+        .await
+        .context("Failed to record a failed login for the username.")?;
+    let ip = rate_limiter
+        .hit(
+            &keys.ip_failures,
+            settings.max_failures_per_ip.saturating_sub(1),
+            settings.failure_window(),
+        )
+        .await
+        .context("Failed to record a failed login for the IP.")?;
+
+    let failures = match (username, ip) {
+        (
+            RateLimitDecision::Allowed { attempts: username },
+            RateLimitDecision::Allowed { attempts: ip },
+        ) => username.max(ip),
+        (username, ip) => {
+            if let RateLimitDecision::Limited { .. } = username {
+                tracing::warn!("Locking out username after too many failed login attempts.");
+                lock_out(
+                    rate_limiter,
+                    &keys.username_lock,
+                    &keys.username_failures,
+                    settings.lockout(),
+                )
+                .await
+                .context("Failed to lock out the username.")?;
+            }
+            if let RateLimitDecision::Limited { .. } = ip {
+                tracing::warn!("Locking out IP after too many failed login attempts.");
+                lock_out(
+                    rate_limiter,
+                    &keys.ip_lock,
+                    &keys.ip_failures,
+                    settings.lockout(),
+                )
+                .await
+                .context("Failed to lock out the IP.")?;
+            }
+            return Ok(Some(settings.lockout()));
+        }
+    };
+    tokio::time::sleep(settings.failure_delay(failures)).await;
+    Ok(None)
+}
+
+async fn lock_out(
+    rate_limiter: &RateLimiter,
+    lock_key: &str,
+    failures_key: &str,
+    lockout: Duration,
+) -> Result<(), redis::RedisError> {
+    rate_limiter.lock(lock_key, lockout).await?;
+    rate_limiter.reset(failures_key).await
+}
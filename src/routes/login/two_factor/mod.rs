@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::two_factor_form;
+pub use post::two_factor_login;
@@ -0,0 +1,109 @@
+use actix_web::error::InternalError;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::audit::{AuditAction, record_audit_event};
+use crate::authentication::two_factor::verify_second_factor;
+use crate::configuration::{LoginSettings, SessionSettings, TwoFactorSettings};
+use crate::rate_limit::RateLimiter;
+use crate::routes::login::post::{LoginError, complete_login, login_redirect, redirect_with_error};
+use crate::routes::login::throttle::{ThrottleKeys, locked_for, record_failure};
+use crate::session_state::TypedSession;
+use crate::utils::client_ip;
+
+/// How long a login that passed the password check waits for its second factor.
+const PENDING_LOGIN_TTL_SECONDS: i64 = 5 * 60;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: Secret<String>,
+}
+
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+skip(form, pool, session, request, rate_limiter, settings, session_settings, two_factor_settings),
+fields(username=tracing::field::Empty, user_id=tracing::field::Empty, ip=tracing::field::Empty)
+)]
+pub async fn two_factor_login(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    request: HttpRequest,
+    rate_limiter: web::Data<RateLimiter>,
+    settings: web::Data<LoginSettings>,
+    session_settings: web::Data<SessionSettings>,
+    two_factor_settings: web::Data<TwoFactorSettings>,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let pending = session
+        .get_pending_login()
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+    let Some(pending) = pending else {
+        return Err(login_redirect(LoginError::ExpiredLoginAttempt));
+    };
+    if Utc::now().timestamp() - pending.started_at > PENDING_LOGIN_TTL_SECONDS {
+        session.remove_pending_login();
+        return Err(login_redirect(LoginError::ExpiredLoginAttempt));
+    }
+    let ip = client_ip(&request);
+    tracing::Span::current().record("username", tracing::field::display(&pending.username));
+    tracing::Span::current().record("user_id", tracing::field::display(&pending.user_id));
+    tracing::Span::current().record("ip", tracing::field::display(&ip));
+
+    let throttle_keys = ThrottleKeys::new(&pending.username, &ip);
+    if let Some(retry_after) = locked_for(&rate_limiter, &throttle_keys)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+    {
+        tracing::warn!("Rejecting a second factor for a locked out username or IP.");
+        session.remove_pending_login();
+        return Err(login_redirect(LoginError::LockedOut { retry_after }));
+    }
+
+    let verified = verify_second_factor(
+        pending.user_id,
+        form.code.expose_secret(),
+        &pool,
+        &two_factor_settings,
+    )
+    .await
+    .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    if verified {
+        session.remove_pending_login();
+        return complete_login(
+            &session,
+            &request,
+            &pool,
+            pending.user_id,
+            true,
+            pending.remember_me,
+            &rate_limiter,
+            &throttle_keys,
+            &session_settings,
+            &two_factor_settings,
+        )
+        .await
+        .map_err(login_redirect);
+    }
+
+    record_audit_event(
+        Some(pending.user_id),
+        AuditAction::LoginFailed,
+        Some(&pending.username),
+        &request,
+        &pool,
+    )
+    .await;
+    match record_failure(&rate_limiter, &settings, &throttle_keys).await {
+        Ok(Some(retry_after)) => {
+            session.remove_pending_login();
+            Err(login_redirect(LoginError::LockedOut { retry_after }))
+        }
+        Ok(None) => Err(redirect_with_error(
+            LoginError::InvalidSecondFactor,
+            "/login/two-factor",
+        )),
+        Err(e) => Err(login_redirect(LoginError::UnexpectedError(e))),
+    }
+}
@@ -0,0 +1,38 @@
+use actix_web::http::header::{AUTHORIZATION, ContentType, WWW_AUTHENTICATE};
+use actix_web::{HttpRequest, HttpResponse, web};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+use crate::metrics::Metrics;
+use crate::startup::MetricsToken;
+use crate::utils::{constant_time_eq, e500};
+
+#[tracing::instrument(name = "Scrape metrics", skip_all)]
+pub async fn scrape_metrics(
+    request: HttpRequest,
+    metrics: web::Data<Metrics>,
+    pool: web::Data<PgPool>,
+    token: web::Data<MetricsToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(expected) = &token.0 {
+        let presented = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        if !presented.is_some_and(|token| constant_time_eq(token, expected.expose_secret())) {
+            return Ok(HttpResponse::Unauthorized()
+                .insert_header((WWW_AUTHENTICATE, "Bearer"))
+                .finish());
+        }
+    }
+    let body = metrics.render(&pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType(
+            prometheus::TEXT_FORMAT
+                .parse()
+                .expect("The Prometheus content type is a valid MIME type"),
+        ))
+        .body(body))
+}
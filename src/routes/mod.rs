@@ -1,13 +1,19 @@
 mod admin;
+mod api;
 mod health_check;
 mod home;
 mod login;
+mod metrics;
+mod setup;
 mod subscriptions;
 mod subscriptions_confirm;
 
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use metrics::*;
+pub use setup::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
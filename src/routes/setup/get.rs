@@ -0,0 +1,63 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use super::not_found;
+use crate::authentication::has_users;
+use crate::startup::BootstrapToken;
+use crate::utils::e500;
+
+/// The first-run page used to create the initial owner account. It only
+/// exists while bootstrapping is enabled and no user has been created yet.
+pub async fn setup_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    bootstrap_token: web::Data<BootstrapToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if bootstrap_token.0.is_none() || has_users(pool.get_ref()).await.map_err(e500)? {
+        return Err(not_found());
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap()
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Setup</title>
+</head>
+<body>
+{msg_html}
+<p>Create the owner account for this blog.</p>
+<form action="/setup" method="post">
+<label>Bootstrap token
+<input type="password" placeholder="Enter the bootstrap token" name="bootstrap_token">
+</label>
+<br>
+<label>Username
+<input type="text" placeholder="Enter Username" name="username">
+</label>
+<br>
+<label>Email
+<input type="email" placeholder="Used for password resets" name="email">
+</label>
+<br>
+<label>Password
+<input type="password" placeholder="Enter Password" name="password">
+</label>
+<br>
+<label>Confirm password
+<input type="password" placeholder="Type the password again" name="password_check">
+</label>
+<br>
+<button type="submit">Create owner</button>
+</form>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,12 @@
+mod get;
+mod post;
+pub use get::setup_form;
+pub use post::setup;
+
+use crate::error::AppError;
+
+/// The setup page pretends not to exist once it has served its purpose,
+/// answering like any unknown page.
+fn not_found() -> actix_web::Error {
+    AppError::NotFound("This page does not exist.".into()).into()
+}
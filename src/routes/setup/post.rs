@@ -0,0 +1,109 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::not_found;
+use crate::audit::{AuditAction, record_audit_event};
+use crate::authentication::{check_password_policy, create_first_owner, has_users};
+use crate::configuration::{PasswordHashingSettings, PasswordPolicySettings};
+use crate::domain::SubscriberEmail;
+use crate::startup::BootstrapToken;
+use crate::utils::{constant_time_eq, e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    bootstrap_token: Secret<String>,
+    username: String,
+    #[serde(default)]
+    email: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Create the first owner",
+    skip(form, request, pool, bootstrap_token, password_policy, hashing),
+    fields(username = %form.username)
+)]
+pub async fn setup(
+    form: web::Form<FormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    bootstrap_token: web::Data<BootstrapToken>,
+    password_policy: web::Data<PasswordPolicySettings>,
+    hashing: web::Data<PasswordHashingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(expected_token) = bootstrap_token.0.as_ref() else {
+        return Err(not_found());
+    };
+    if has_users(pool.get_ref()).await.map_err(e500)? {
+        return Err(not_found());
+    }
+    if !constant_time_eq(
+        expected_token.expose_secret(),
+        form.bootstrap_token.expose_secret(),
+    ) {
+        tracing::warn!("Rejecting a setup attempt with an invalid bootstrap token.");
+        FlashMessage::error("Invalid bootstrap token.").send();
+        return Ok(see_other("/setup"));
+    }
+
+    let username = form.username.trim();
+    if username.is_empty() || username.graphemes(true).count() > 256 {
+        FlashMessage::error("Usernames must be between 1 and 256 characters long.").send();
+        return Ok(see_other("/setup"));
+    }
+    let email = form.email.trim();
+    let email = if email.is_empty() {
+        None
+    } else {
+        match SubscriberEmail::parse(email.to_string()) {
+            Ok(email) => Some(email),
+            Err(_) => {
+                FlashMessage::error("Please enter a valid email address.").send();
+                return Ok(see_other("/setup"));
+            }
+        }
+    };
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other("/setup"));
+    }
+    let violations = check_password_policy(&password_policy, &form.password, Some(username), None)
+        .await
+        .map_err(e500)?;
+    if !violations.is_empty() {
+        for violation in violations {
+            FlashMessage::error(violation.to_string()).send();
+        }
+        return Ok(see_other("/setup"));
+    }
+
+    match create_first_owner(
+        username,
+        email.as_ref(),
+        form.password.clone(),
+        &hashing,
+        &pool,
+    )
+    .await
+    .map_err(e500)?
+    {
+        Some(user_id) => {
+            record_audit_event(
+                Some(user_id),
+                AuditAction::SetupCompleted,
+                Some(username),
+                &request,
+                &pool,
+            )
+            .await;
+            FlashMessage::info("The owner account has been created, you can now log in.").send();
+            Ok(see_other("/login"))
+        }
+        None => Err(not_found()),
+    }
+}
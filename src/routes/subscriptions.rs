@@ -1,78 +1,191 @@
-use actix_web::{HttpResponse, ResponseError, http::StatusCode, web};
+use actix_web::http::header::USER_AGENT;
+use actix_web::{HttpRequest, HttpResponse, web};
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
 use rand::{Rng, distr::Alphanumeric, rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::time::Duration;
 use uuid::Uuid;
 
 use crate::{
+    configuration::SubscriptionSettings,
     domain::{NewSubscriber, SubscriberEmail, SubscriberName},
     email_client::EmailClient,
-    startup::ApplicationBaseUrl,
-    utils::error_chain_fmt,
+    error::AppError,
+    rate_limit::{RateLimitDecision, RateLimiter},
+    request_id::current_request_id,
+    startup::{ApplicationBaseUrl, HmacSecret},
+    utils::{client_ip, error_chain_fmt},
 };
 
+/// The consent wording displayed next to the subscription form.
+pub const CONSENT_TEXT: &str =
+    "By subscribing you agree to receive our newsletter and related emails at this address.";
+/// Version of `CONSENT_TEXT`. Bump it whenever that wording changes so that
+/// the consent log keeps pointing at the exact text each subscriber agreed to.
+pub const CONSENT_TEXT_VERSION: &str = "2026-10-19";
+const DEFAULT_CONSENT_SOURCE: &str = "website";
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     name: String,
     email: String,
+    source: Option<String>,
+    /// Honeypot hidden from humans: any value means a bot filled in the form.
+    contact_me_by_fax_only: Option<String>,
+    /// Signed timestamp of when the form was rendered, see `sign_form_timestamp`.
+    form_token: Option<String>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
-
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let email = SubscriberEmail::parse(value.email)?;
-        let name = SubscriberName::parse(value.name)?;
+/// How and where consent to receive the newsletter was obtained.
+pub struct SubscriptionConsent {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+}
 
-        Ok(Self { email, name })
+impl SubscriptionConsent {
+    fn from_request(request: &HttpRequest, source: Option<String>) -> Self {
+        // The peer address, as forwarding headers are set by the client
+        let ip_address = Some(client_ip(request)).filter(|ip| !ip.is_empty());
+        let user_agent = request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(ToOwned::to_owned);
+        let source = source
+            .map(|s| s.trim().chars().take(64).collect::<String>())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| DEFAULT_CONSENT_SOURCE.into());
+        Self {
+            ip_address,
+            user_agent,
+            source,
+        }
     }
 }
 
-#[derive(thiserror::Error)]
-pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
+/// Produce the value of the `form_token` field embedded in the subscription form.
+pub fn sign_form_timestamp(secret: &Secret<String>, rendered_at: DateTime<Utc>) -> String {
+    let rendered_at = rendered_at.timestamp_millis();
+    let signature = form_timestamp_mac(secret, rendered_at)
+        .finalize()
+        .into_bytes();
+    format!("{}.{}", rendered_at, hex::encode(signature))
 }
 
-impl From<String> for SubscribeError {
-    fn from(e: String) -> Self {
-        Self::ValidationError(e)
-    }
+fn verify_form_timestamp(secret: &Secret<String>, token: &str) -> Result<DateTime<Utc>, String> {
+    let (rendered_at, signature) = token
+        .split_once('.')
+        .ok_or_else(|| "Malformed form token".to_string())?;
+    let rendered_at: i64 = rendered_at
+        .parse()
+        .map_err(|_| "Malformed form token".to_string())?;
+    let signature = hex::decode(signature).map_err(|_| "Malformed form token".to_string())?;
+    form_timestamp_mac(secret, rendered_at)
+        .verify_slice(&signature)
+        .map_err(|_| "Invalid form token".to_string())?;
+    DateTime::from_timestamp_millis(rendered_at).ok_or_else(|| "Invalid form token".to_string())
 }
-impl std::fmt::Debug for SubscribeError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
-    }
+
+fn form_timestamp_mac(secret: &Secret<String>, rendered_at: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes()).unwrap();
+    mac.update(b"subscription-form:");
+    mac.update(rendered_at.to_string().as_bytes());
+    mac
 }
 
-impl ResponseError for SubscribeError {
-    fn status_code(&self) -> StatusCode {
-        match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
-            SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        }
+impl TryFrom<FormData> for NewSubscriber {
+    type Error = String;
+
+    fn try_from(value: FormData) -> Result<Self, Self::Error> {
+        let email = SubscriberEmail::parse(value.email)?;
+        let name = SubscriberName::parse(value.name)?;
+
+        Ok(Self { email, name })
     }
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, db_pool, email_client, base_url),
+    skip(form, db_pool, email_client, base_url, request, rate_limiter, settings, hmac_secret),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
     )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber: NewSubscriber =
-        form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    request: HttpRequest,
+    rate_limiter: web::Data<RateLimiter>,
+    settings: web::Data<SubscriptionSettings>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, AppError> {
+    if form
+        .contact_me_by_fax_only
+        .as_deref()
+        .is_some_and(|v| !v.is_empty())
+    {
+        tracing::warn!("Dropping a subscription attempt that filled in the honeypot field.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    if !settings.min_form_fill_time().is_zero() {
+        let token = form
+            .form_token
+            .as_deref()
+            .ok_or_else(|| AppError::BadRequest("Missing form token".into()))?;
+        let rendered_at =
+            verify_form_timestamp(&hmac_secret.0, token).map_err(AppError::BadRequest)?;
+        // Negative when the token claims to come from the future
+        let elapsed = (Utc::now() - rendered_at)
+            .to_std()
+            .map_err(|_| AppError::BadRequest("Invalid form token".into()))?;
+        if elapsed > settings.max_form_age() {
+            return Err(AppError::BadRequest(
+                "The form has expired, reload the page and try again".into(),
+            ));
+        }
+        if elapsed < settings.min_form_fill_time() {
+            tracing::warn!(
+                elapsed_milliseconds = elapsed.as_millis() as u64,
+                "Dropping a subscription attempt submitted faster than a human could."
+            );
+            return Ok(HttpResponse::Ok().finish());
+        }
+    }
+
+    let ip = client_ip(&request);
+    if !ip.is_empty() {
+        check_rate_limit(
+            &rate_limiter,
+            &format!("subscriptions:ip:{}", ip),
+            settings.max_attempts_per_ip,
+            settings.rate_limit_window(),
+        )
+        .await?;
+    }
+
+    let consent = SubscriptionConsent::from_request(&request, form.source.clone());
+    let new_subscriber: NewSubscriber = form.0.try_into().map_err(AppError::BadRequest)?;
+
+    let email_digest = hex::encode(Sha256::digest(
+        new_subscriber.email.as_ref().to_lowercase().as_bytes(),
+    ));
+    check_rate_limit(
+        &rate_limiter,
+        &format!("subscriptions:email:{}", email_digest),
+        settings.max_attempts_per_email,
+        settings.rate_limit_window(),
+    )
+    .await?;
+
     let mut transaction = db_pool
         .begin()
         .await
@@ -80,6 +193,9 @@ pub async fn subscribe(
     let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert a new subscriber.")?;
+    store_consent(&mut transaction, subscriber_id, &consent)
+        .await
+        .context("Failed to store the subscriber's consent.")?;
     let token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &token)
         .await
@@ -104,15 +220,17 @@ pub async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
+    let request_id = current_request_id();
     let query = sqlx::query!(
         r#"
-            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-            VALUES ($1, $2, $3, $4, 'pending_confirmation')
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, request_id)
+            VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        request_id.as_ref().map(|id| id.as_str()),
     );
     transaction.execute(query).await.map_err(|e| {
         tracing::error!("failed to execute query: {:?}", e);
@@ -121,6 +239,36 @@ pub async fn insert_subscriber(
     Ok(subscriber_id)
 }
 
+#[tracing::instrument(
+    name = "Saving subscription consent in the database",
+    skip(transaction, consent)
+)]
+pub async fn store_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    consent: &SubscriptionConsent,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+            INSERT INTO subscription_consents
+                (id, subscriber_id, consented_at, ip_address, user_agent, source, consent_text_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        Utc::now(),
+        consent.ip_address,
+        consent.user_agent,
+        consent.source,
+        CONSENT_TEXT_VERSION,
+    );
+    transaction.execute(query).await.map_err(|e| {
+        tracing::error!("failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
 #[tracing::instrument(
     name = "Send confirmation email after saving the user in database",
     skip(email_client, new_subscriber, base_url, token)
@@ -152,7 +300,29 @@ pub async fn send_confirmation_email(
         .await
 }
 
-fn generate_subscription_token() -> String {
+async fn check_rate_limit(
+    rate_limiter: &RateLimiter,
+    key: &str,
+    max_attempts: u64,
+    window: Duration,
+) -> Result<(), AppError> {
+    match rate_limiter
+        .hit(key, max_attempts, window)
+        .await
+        .context("Failed to check the subscription rate limit.")?
+    {
+        RateLimitDecision::Allowed { .. } => Ok(()),
+        RateLimitDecision::Limited { retry_after } => {
+            tracing::warn!(rate_limit_key = %key, "Subscription rate limit exceeded.");
+            Err(AppError::TooManyRequests {
+                message: "Too many subscription attempts, try again later.".into(),
+                retry_after,
+            })
+        }
+    }
+}
+
+pub fn generate_subscription_token() -> String {
     let mut rng = rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric) as char)
         .take(50)
@@ -163,7 +333,7 @@ fn generate_subscription_token() -> String {
     name = "Store confirmation token in database",
     skip(transaction, subscriber_id, token)
 )]
-async fn store_token(
+pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
     token: &str,
@@ -1,6 +1,8 @@
+use crate::error::AppError;
 use crate::utils::error_chain_fmt;
-use actix_web::{HttpResponse, ResponseError, http::StatusCode, web};
+use actix_web::{HttpResponse, web};
 use anyhow::Context;
+use chrono::Utc;
 use serde::Deserialize;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
@@ -10,34 +12,6 @@ pub struct Parameters {
     subscription_token: String,
 }
 
-#[derive(thiserror::Error)]
-pub enum SubscriptionConfirmError {
-    #[error("{0}")]
-    ValidationError(String),
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
-}
-
-impl From<String> for SubscriptionConfirmError {
-    fn from(e: String) -> Self {
-        Self::ValidationError(e)
-    }
-}
-impl std::fmt::Debug for SubscriptionConfirmError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
-    }
-}
-
-impl ResponseError for SubscriptionConfirmError {
-    fn status_code(&self) -> StatusCode {
-        match self {
-            SubscriptionConfirmError::ValidationError(_) => StatusCode::BAD_REQUEST,
-            SubscriptionConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        }
-    }
-}
-
 pub struct ConfirmTokenError(sqlx::Error);
 
 impl std::fmt::Debug for ConfirmTokenError {
@@ -65,7 +39,7 @@ impl std::error::Error for ConfirmTokenError {
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
-) -> Result<HttpResponse, SubscriptionConfirmError> {
+) -> Result<HttpResponse, AppError> {
     let mut transaction = pool
         .begin()
         .await
@@ -75,9 +49,7 @@ pub async fn confirm(
         get_subscriber_id_from_token(&mut transaction, &parameters.subscription_token)
             .await
             .context("failed to fetch subscriber id")?
-            .ok_or_else(|| {
-                SubscriptionConfirmError::ValidationError("Invalid subscription token".into())
-            })?;
+            .ok_or_else(|| AppError::BadRequest("Invalid subscription token".into()))?;
 
     confirm_subscriber(&mut transaction, subscriber_id)
         .await
@@ -137,5 +109,21 @@ async fn confirm_subscriber(
         e
     })?;
 
+    sqlx::query!(
+        r#"
+        UPDATE subscription_consents
+        SET confirmed_at = $1
+        WHERE subscriber_id = $2 AND confirmed_at IS NULL
+        "#,
+        Utc::now(),
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
     Ok(())
 }
@@ -2,12 +2,30 @@ use std::future::{Ready, ready};
 
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::FromRequest;
+use rand::{Rng, distr::Alphanumeric, rng};
+use secrecy::{ExposeSecret, Secret};
 use uuid::Uuid;
 
 pub struct TypedSession(Session);
 
+/// A login that passed the password check and is waiting for its second factor.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PendingLogin {
+    pub user_id: Uuid,
+    pub username: String,
+    /// Unix timestamp of the password check.
+    pub started_at: i64,
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
+    const PENDING_LOGIN_KEY: &'static str = "pending_login";
+    const PENDING_TOTP_SECRET_KEY: &'static str = "pending_totp_secret";
+    const TWO_FACTOR_ENROLLMENT_REQUIRED_KEY: &'static str = "two_factor_enrollment_required";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -20,6 +38,84 @@ impl TypedSession {
     pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
         self.0.get(Self::USER_ID_KEY)
     }
+
+    /// The id this session is registered under in `user_sessions`.
+    pub fn insert_session_id(&self, session_id: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
+    /// The CSRF token to embed in forms, generated on first use.
+    pub fn csrf_token(&self) -> Result<String, anyhow::Error> {
+        if let Some(token) = self.get_csrf_token()? {
+            return Ok(token);
+        }
+        let mut rng = rng();
+        let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric) as char)
+            .take(32)
+            .collect();
+        self.0.insert(Self::CSRF_TOKEN_KEY, &token)?;
+        Ok(token)
+    }
+
+    pub fn insert_pending_login(&self, pending: &PendingLogin) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_LOGIN_KEY, pending)
+    }
+
+    pub fn get_pending_login(&self) -> Result<Option<PendingLogin>, SessionGetError> {
+        self.0.get(Self::PENDING_LOGIN_KEY)
+    }
+
+    pub fn remove_pending_login(&self) {
+        self.0.remove(Self::PENDING_LOGIN_KEY);
+    }
+
+    pub fn insert_pending_totp_secret(
+        &self,
+        secret: &Secret<String>,
+    ) -> Result<(), SessionInsertError> {
+        self.0
+            .insert(Self::PENDING_TOTP_SECRET_KEY, secret.expose_secret())
+    }
+
+    pub fn get_pending_totp_secret(&self) -> Result<Option<Secret<String>>, SessionGetError> {
+        Ok(self
+            .0
+            .get::<String>(Self::PENDING_TOTP_SECRET_KEY)?
+            .map(Secret::new))
+    }
+
+    pub fn remove_pending_totp_secret(&self) {
+        self.0.remove(Self::PENDING_TOTP_SECRET_KEY);
+    }
+
+    pub fn set_two_factor_enrollment_required(
+        &self,
+        required: bool,
+    ) -> Result<(), SessionInsertError> {
+        if required {
+            self.0
+                .insert(Self::TWO_FACTOR_ENROLLMENT_REQUIRED_KEY, true)
+        } else {
+            self.0.remove(Self::TWO_FACTOR_ENROLLMENT_REQUIRED_KEY);
+            Ok(())
+        }
+    }
+
+    pub fn two_factor_enrollment_required(&self) -> Result<bool, SessionGetError> {
+        Ok(self
+            .0
+            .get(Self::TWO_FACTOR_ENROLLMENT_REQUIRED_KEY)?
+            .unwrap_or(false))
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
@@ -1,41 +1,62 @@
 use actix_session::SessionMiddleware;
+use actix_session::config::{BrowserSession, TtlExtensionPolicy};
 use actix_session::storage::RedisSessionStore;
-use actix_web::cookie::Key;
+use actix_web::cookie::{Key, time::Duration as CookieDuration};
 use actix_web::dev::Server;
 use actix_web::middleware::from_fn;
 use actix_web::{App, HttpServer, web};
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_flash_messages::storage::CookieMessageStore;
+use anyhow::Context;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 use sqlx::postgres::PgPoolOptions;
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
+use std::time::Duration;
 
-use crate::authentication::reject_anonymous_users;
+use crate::authentication::{
+    Permission, persist_remembered_sessions, reject_anonymous_users, reject_api_tokens,
+    reject_invalid_csrf_tokens, reject_unauthenticated_api_requests, require_permission,
+    require_two_factor_enrollment,
+};
+use crate::background::BackgroundTasks;
 use crate::configuration::{DatabaseSettings, Settings};
 use crate::email_client::EmailClient;
+use crate::error::{page_not_found, render_error_pages};
+use crate::metrics::{InstrumentedSessionStore, Metrics, record_http_metrics};
+use crate::migrations::{check_database_is_not_ahead, run_migrations};
+use crate::oidc_client::OidcClient;
+use crate::rate_limit::RateLimiter;
+use crate::request_id::{RequestIdRootSpanBuilder, propagate_request_id};
 use crate::routes::*;
 
 pub struct ApplicationBaseUrl(pub String);
 pub struct Application {
     port: u16,
     server: Server,
+    metrics_port: Option<u16>,
+    metrics_server: Option<Server>,
+    db_pool: PgPool,
+    read_pool: ReadPool,
+    background_tasks: BackgroundTasks,
+    shutdown_timeout: Duration,
 }
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
-        let timeout = configuration.email_client.timeout();
+        if configuration.database.run_migrations {
+            run_migrations(&configuration.database).await?;
+        }
         let connection_pool = get_connection_pool(&configuration.database);
-        let sender_email = configuration
+        check_database_is_not_ahead(&connection_pool).await?;
+        let read_pool = ReadPool::new(&connection_pool, &configuration.database);
+        let metrics = Metrics::new().context("Failed to register the metrics")?;
+        let background_tasks = BackgroundTasks::default();
+        let shutdown_timeout = Duration::from_secs(configuration.shutdown_timeout_seconds);
+        let email_client = configuration
             .email_client
-            .sender()
-            .expect("failed to get sender email");
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.auth_token,
-            timeout,
-        );
+            .client()?
+            .with_metrics(metrics.clone());
 
         let address = format!(
             "{}:{}",
@@ -44,82 +65,426 @@ impl Application {
         let listener: TcpListener = TcpListener::bind(address)?;
         let port = listener.local_addr().expect("failed to local addr").port();
         println!("starting server on port: {}", port);
+
+        let (metrics_port, metrics_server) = match configuration.metrics.port {
+            Some(metrics_port) => {
+                let listener = TcpListener::bind(format!(
+                    "{}:{}",
+                    configuration.application_host, metrics_port
+                ))?;
+                let metrics_port = listener.local_addr()?.port();
+                let server = run_metrics(
+                    listener,
+                    connection_pool.clone(),
+                    metrics.clone(),
+                    configuration.metrics.bearer_token.clone(),
+                )?;
+                (Some(metrics_port), Some(server))
+            }
+            None => (None, None),
+        };
         let server = run(
             listener,
-            connection_pool,
+            connection_pool.clone(),
+            read_pool.clone(),
             email_client,
-            configuration.base_url,
-            configuration.hmac_secret,
-            configuration.redis_uri,
+            metrics,
+            background_tasks.clone(),
+            configuration,
         )
         .await?;
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            metrics_port,
+            metrics_server,
+            db_pool: connection_pool,
+            read_pool,
+            background_tasks,
+            shutdown_timeout,
+        })
     }
     pub fn port(&self) -> u16 {
         self.port
     }
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+    /// The port `GET /metrics` is served on, when it is not the application
+    /// port.
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.metrics_port
+    }
+    /// Serve until a stop signal. On SIGTERM or SIGINT the listeners close,
+    /// in-flight requests get the shutdown timeout to finish, then background
+    /// tasks get as long again before the database pool is closed.
+    pub async fn run_until_stopped(self) -> Result<(), anyhow::Error> {
+        let served = match self.metrics_server {
+            Some(metrics_server) => tokio::try_join!(self.server, metrics_server).map(|_| ()),
+            None => self.server.await,
+        };
+        tracing::info!("The HTTP server stopped, waiting for background tasks.");
+        let drained = self.background_tasks.shutdown(self.shutdown_timeout).await;
+        self.db_pool.close().await;
+        if let Some(replica) = self.read_pool.replica() {
+            replica.close().await;
+        }
+        served.context("The HTTP server failed")?;
+        if !drained {
+            anyhow::bail!("Background tasks were still running at the shutdown deadline");
+        }
+        Ok(())
     }
 }
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
-    PgPoolOptions::new().connect_lazy_with(configuration.with_db())
+    pool_options(configuration).connect_lazy_with(configuration.with_db())
+}
+
+fn pool_options(configuration: &DatabaseSettings) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(configuration.max_connections)
+        .min_connections(configuration.min_connections)
+        .acquire_timeout(configuration.acquire_timeout())
+        .idle_timeout(configuration.idle_timeout())
+}
+
+/// Where read-only queries go: the read replica when one is configured, the
+/// primary otherwise. Replicas lag a little, so anything that must see a
+/// write just made belongs on the primary.
+#[derive(Clone)]
+pub struct ReadPool {
+    pool: PgPool,
+    is_replica: bool,
+}
+
+impl ReadPool {
+    pub fn new(primary: &PgPool, configuration: &DatabaseSettings) -> Self {
+        match configuration.read_replica() {
+            Some(options) => Self {
+                pool: pool_options(configuration).connect_lazy_with(options),
+                is_replica: true,
+            },
+            None => Self {
+                pool: primary.clone(),
+                is_replica: false,
+            },
+        }
+    }
+
+    /// The replica's pool, if read-only queries do not simply go to the
+    /// primary.
+    pub fn replica(&self) -> Option<&PgPool> {
+        self.is_replica.then_some(&self.pool)
+    }
+}
+
+impl std::ops::Deref for ReadPool {
+    type Target = PgPool;
+
+    fn deref(&self) -> &PgPool {
+        &self.pool
+    }
 }
 
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
+    read_pool: ReadPool,
     email_client: EmailClient,
-    base_url: String,
-    hmac_secret: Secret<String>,
-    redis_uri: Secret<String>,
+    metrics: Metrics,
+    background_tasks: BackgroundTasks,
+    configuration: Settings,
 ) -> Result<Server, anyhow::Error> {
+    let Settings {
+        base_url,
+        hmac_secret,
+        redis_uri,
+        redis_key_prefix,
+        subscriptions: subscription_settings,
+        login: login_settings,
+        session: session_settings,
+        two_factor: two_factor_settings,
+        password_reset: password_reset_settings,
+        password_policy,
+        password_hashing,
+        health: health_settings,
+        bootstrap_token,
+        oidc,
+        shutdown_timeout_seconds,
+        metrics: metrics_settings,
+        trusted_proxies,
+        ..
+    } = configuration;
     let db_pool = web::Data::new(db_pool);
+    let read_pool = web::Data::new(read_pool);
     let email_client = web::Data::new(email_client);
+    let oidc_client = web::Data::new(oidc.map(|settings| {
+        let timeout = settings.timeout();
+        OidcClient::new(settings, &base_url, timeout)
+    }));
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let metrics = web::Data::new(metrics);
+    let background_tasks = web::Data::new(background_tasks);
+    // Unauthenticated metrics are only served on the dedicated listener,
+    // which is kept off the public network
+    let serve_metrics = metrics_settings.port.is_none() && metrics_settings.bearer_token.is_some();
+    let metrics_token = web::Data::new(MetricsToken(metrics_settings.bearer_token));
+    let trusted_proxies = web::Data::new(TrustedProxies(trusted_proxies));
 
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store =
         CookieMessageStore::builder(Key::from(hmac_secret.expose_secret().as_bytes())).build();
 
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let rate_limiter = web::Data::new(RateLimiter::new(&redis_uri, redis_key_prefix).await?);
+    let subscription_settings = web::Data::new(subscription_settings);
+    let login_settings = web::Data::new(login_settings);
+    let two_factor_settings = web::Data::new(two_factor_settings);
+    let password_reset_settings = web::Data::new(password_reset_settings);
+    let password_policy = web::Data::new(password_policy);
+    let password_hashing = web::Data::new(password_hashing);
+    let health_settings = web::Data::new(health_settings);
+    let readiness_cache = web::Data::new(ReadinessCache::default());
+
+    let session_lifecycle = BrowserSession::default()
+        .state_ttl(
+            CookieDuration::try_from(session_settings.state_ttl())
+                .context("The session lifetime is too long")?,
+        )
+        .state_ttl_extension_policy(if session_settings.rolling {
+            TtlExtensionPolicy::OnEveryRequest
+        } else {
+            TtlExtensionPolicy::OnStateChanges
+        });
+    let session_settings = web::Data::new(session_settings);
 
     let message_framework = FlashMessagesFramework::builder(message_store).build();
 
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
-            .wrap(tracing_actix_web::TracingLogger::default())
+            .wrap(
+                SessionMiddleware::builder(
+                    InstrumentedSessionStore::new(redis_store.clone(), metrics.get_ref().clone()),
+                    secret_key.clone(),
+                )
+                .cookie_name(session_settings.cookie_name.clone())
+                .cookie_domain(session_settings.cookie_domain.clone())
+                .cookie_secure(session_settings.cookie_secure)
+                .cookie_same_site(session_settings.cookie_same_site.into())
+                .session_lifecycle(session_lifecycle.clone())
+                .build(),
+            )
+            .wrap(from_fn(persist_remembered_sessions))
+            .wrap(tracing_actix_web::TracingLogger::<RequestIdRootSpanBuilder>::new())
+            .wrap(from_fn(record_http_metrics))
+            .wrap(from_fn(render_error_pages))
+            .wrap(from_fn(propagate_request_id))
+            .configure(|cfg| {
+                if serve_metrics {
+                    cfg.route("/metrics", web::get().to(scrape_metrics));
+                }
+            })
             .route("/health", web::get().to(health_check))
+            .route("/health/live", web::get().to(liveness))
+            .route("/health/ready", web::get().to(readiness))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/login/oidc", web::get().to(oidc_login))
+            .route("/login/oidc/callback", web::get().to(oidc_callback))
+            .route("/login/two-factor", web::get().to(two_factor_form))
+            .route("/login/two-factor", web::post().to(two_factor_login))
+            .route("/login/forgot", web::get().to(forgot_password_form))
+            .route("/login/forgot", web::post().to(request_password_reset))
+            .route("/login/reset", web::get().to(reset_password_form))
+            .route("/login/reset", web::post().to(reset_password))
+            .route("/setup", web::get().to(setup_form))
+            .route("/setup", web::post().to(setup))
             .route("/", web::get().to(home))
             .service(
                 web::scope("/admin")
+                    .wrap(from_fn(reject_invalid_csrf_tokens))
+                    .wrap(from_fn(require_two_factor_enrollment))
                     .wrap(from_fn(reject_anonymous_users))
-                    .route("/dashboard", web::get().to(admin_dashboard))
-                    .route("/password", web::get().to(change_password_form))
-                    .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(log_out))
-                    .route("/newsletters", web::get().to(newsletter_publish_form))
-                    .route("/newsletters", web::post().to(publish_newsletter)),
+                    .route(
+                        "/dashboard",
+                        web::get()
+                            .to(admin_dashboard)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .service(
+                        web::scope("/password")
+                            .wrap(from_fn(reject_api_tokens))
+                            .route("", web::get().to(change_password_form))
+                            .route("", web::post().to(change_password)),
+                    )
+                    .route(
+                        "/logout",
+                        web::post().to(log_out).wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/newsletters",
+                        web::get()
+                            .to(newsletter_publish_form)
+                            .wrap(from_fn(|req, next| {
+                                require_permission(Permission::DraftNewsletters, req, next)
+                            })),
+                    )
+                    .route(
+                        "/newsletters",
+                        web::post()
+                            .to(publish_newsletter)
+                            .wrap(from_fn(|req, next| {
+                                require_permission(Permission::PublishNewsletters, req, next)
+                            })),
+                    )
+                    .route(
+                        "/newsletters/drafts",
+                        web::post()
+                            .to(save_newsletter_draft)
+                            .wrap(from_fn(|req, next| {
+                                require_permission(Permission::DraftNewsletters, req, next)
+                            })),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/publish",
+                        web::post()
+                            .to(publish_newsletter_draft)
+                            .wrap(from_fn(|req, next| {
+                                require_permission(Permission::PublishNewsletters, req, next)
+                            })),
+                    )
+                    .service(
+                        web::scope("/subscribers")
+                            .wrap(from_fn(|req, next| {
+                                require_permission(Permission::ViewSubscribers, req, next)
+                            }))
+                            .route("", web::get().to(subscribers_list))
+                            .route("/{subscriber_id}", web::get().to(subscriber_detail)),
+                    )
+                    .service(
+                        web::scope("/users")
+                            .wrap(from_fn(|req, next| {
+                                require_permission(Permission::ManageUsers, req, next)
+                            }))
+                            .route("", web::get().to(users_list))
+                            .route("", web::post().to(invite_user))
+                            .route("/{user_id}/role", web::post().to(change_user_role))
+                            .route("/{user_id}/email", web::post().to(change_user_email))
+                            .route("/{user_id}/activate", web::post().to(activate_user))
+                            .route("/{user_id}/deactivate", web::post().to(deactivate_user))
+                            .route("/{user_id}/delete", web::post().to(delete_user)),
+                    )
+                    .service(
+                        web::scope("/audit")
+                            .wrap(from_fn(|req, next| {
+                                require_permission(Permission::ViewAuditLog, req, next)
+                            }))
+                            .route("", web::get().to(audit_log))
+                            .route("/export", web::get().to(export_audit_log)),
+                    )
+                    .service(
+                        web::scope("/sessions")
+                            .wrap(from_fn(reject_api_tokens))
+                            .route("", web::get().to(sessions_list))
+                            .route("/revoke-others", web::post().to(revoke_other_sessions))
+                            .route("/{session_id}/revoke", web::post().to(revoke_one_session)),
+                    )
+                    .service(
+                        web::scope("/api-tokens")
+                            .wrap(from_fn(reject_api_tokens))
+                            .route("", web::get().to(api_tokens_list))
+                            .route("", web::post().to(create_token))
+                            .route("/{token_id}/revoke", web::post().to(revoke_token)),
+                    )
+                    .service(
+                        web::scope("/two-factor")
+                            .wrap(from_fn(reject_api_tokens))
+                            .route("", web::get().to(manage_two_factor_form))
+                            .route("", web::post().to(enroll_two_factor))
+                            .route("/disable", web::post().to(unenroll_two_factor)),
+                    ),
+            )
+            .service(
+                web::scope("/api")
+                    .wrap(from_fn(reject_invalid_csrf_tokens))
+                    .wrap(from_fn(reject_unauthenticated_api_requests))
+                    .route(
+                        "/newsletters",
+                        web::post()
+                            .to(api_publish_newsletter)
+                            .wrap(from_fn(|req, next| {
+                                require_permission(Permission::PublishNewsletters, req, next)
+                            })),
+                    )
+                    .route(
+                        "/newsletters/drafts",
+                        web::post()
+                            .to(api_save_newsletter_draft)
+                            .wrap(from_fn(|req, next| {
+                                require_permission(Permission::DraftNewsletters, req, next)
+                            })),
+                    ),
             )
+            .default_service(web::to(page_not_found))
             .app_data(db_pool.clone())
+            .app_data(read_pool.clone())
             .app_data(email_client.clone())
+            .app_data(background_tasks.clone())
+            .app_data(metrics.clone())
+            .app_data(metrics_token.clone())
+            .app_data(oidc_client.clone())
             .app_data(base_url.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(subscription_settings.clone())
+            .app_data(login_settings.clone())
+            .app_data(session_settings.clone())
+            .app_data(two_factor_settings.clone())
+            .app_data(password_reset_settings.clone())
+            .app_data(password_policy.clone())
+            .app_data(password_hashing.clone())
+            .app_data(health_settings.clone())
+            .app_data(readiness_cache.clone())
+            .app_data(trusted_proxies.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
+            .app_data(web::Data::new(BootstrapToken(bootstrap_token.clone())))
     })
+    .shutdown_timeout(shutdown_timeout_seconds)
     .listen(listener)?
     .run();
 
     Ok(server)
 }
 
+/// Serve only `GET /metrics` on `listener`, for scrapers kept apart from the
+/// application's own traffic.
+pub fn run_metrics(
+    listener: TcpListener,
+    db_pool: PgPool,
+    metrics: Metrics,
+    bearer_token: Option<Secret<String>>,
+) -> Result<Server, anyhow::Error> {
+    let db_pool = web::Data::new(db_pool);
+    let metrics = web::Data::new(metrics);
+    let metrics_token = web::Data::new(MetricsToken(bearer_token));
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(tracing_actix_web::TracingLogger::default())
+            .route("/metrics", web::get().to(scrape_metrics))
+            .app_data(db_pool.clone())
+            .app_data(metrics.clone())
+            .app_data(metrics_token.clone())
+    })
+    .listen(listener)?
+    .run();
+    Ok(server)
+}
+
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
+
+pub struct BootstrapToken(pub Option<Secret<String>>);
+
+pub struct MetricsToken(pub Option<Secret<String>>);
+
+/// See `Settings::trusted_proxies`.
+pub struct TrustedProxies(pub Vec<IpAddr>);
@@ -1,3 +1,10 @@
+use anyhow::Context;
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider, Tracer};
 use tokio::task::JoinHandle;
 use tracing::{Subscriber, subscriber::set_global_default};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
@@ -5,10 +12,15 @@ use tracing_log::LogTracer;
 use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt};
 
+use crate::configuration::OtlpSettings;
+
+/// Compose the logging subscriber, also exporting spans through `tracer` when
+/// one is given.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    tracer: Option<Tracer>,
 ) -> impl Subscriber + Send + Sync
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
@@ -21,11 +33,46 @@ where
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Build the provider batching spans to the OTLP collector and make it the
+/// global one. Shut it down before exiting to flush the last batch.
+pub fn init_otlp_tracer_provider(
+    settings: &OtlpSettings,
+) -> Result<SdkTracerProvider, anyhow::Error> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&settings.endpoint)
+        .build()
+        .context("Failed to build the OTLP span exporter")?;
+    // Honour the sampling decision of whoever started the trace upstream
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+        settings.sampling_ratio,
+    )));
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(sampler)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(settings.service_name.clone())
+                .build(),
+        )
+        .build();
+    global::set_tracer_provider(provider.clone());
+    Ok(provider)
+}
+
+/// The tracer spans are exported through.
+pub fn get_tracer(provider: &SdkTracerProvider) -> Tracer {
+    provider.tracer(env!("CARGO_PKG_NAME"))
 }
 
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     // redirect all logs events to tracing
     LogTracer::init().expect("failed to initialize log tracer");
+    // Read and write W3C `traceparent` headers
+    global::set_text_map_propagator(TraceContextPropagator::new());
     set_global_default(subscriber).expect("failed to set subscriber");
 }
 
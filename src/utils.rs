@@ -1,4 +1,9 @@
-use actix_web::{HttpResponse, http::header::LOCATION};
+use actix_web::http::header::{FORWARDED, HeaderName, LOCATION};
+use actix_web::{HttpRequest, HttpResponse, web};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::error::AppError;
+use crate::startup::TrustedProxies;
 
 pub fn error_chain_fmt(
     e: &impl std::error::Error,
@@ -15,12 +20,87 @@ pub fn error_chain_fmt(
 
 pub fn e500<T>(e: T) -> actix_web::Error
 where
-    T: std::fmt::Debug + std::fmt::Display + 'static,
+    T: Into<anyhow::Error>,
 {
-    actix_web::error::ErrorInternalServerError(e)
+    AppError::Unexpected(e.into()).into()
 }
 pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// Compare two secrets without leaking where they first differ through timing.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// The address a request came from, as recorded for throttling and auditing.
+/// Forwarding headers are only believed when the peer is a trusted proxy, as
+/// anyone else can set them to whatever they like.
+pub fn client_ip(request: &HttpRequest) -> String {
+    let Some(peer) = request.peer_addr().map(|addr| addr.ip()) else {
+        return String::new();
+    };
+    let trusted = request
+        .app_data::<web::Data<TrustedProxies>>()
+        .map(|proxies| proxies.0.as_slice())
+        .unwrap_or_default();
+    if !trusted.contains(&peer) {
+        return peer.to_string();
+    }
+    // Each proxy appends the address it got the request from, so walk back
+    // from the nearest hop and stop at the first one no trusted proxy sent
+    for hop in forwarded_for(request).iter().rev() {
+        match hop {
+            Some(ip) if trusted.contains(ip) => continue,
+            Some(ip) => return ip.to_string(),
+            // An obfuscated or unknown hop hides everything behind it
+            None => break,
+        }
+    }
+    peer.to_string()
+}
+
+/// The chain of client addresses from `Forwarded`, or failing that from
+/// `X-Forwarded-For`, nearest hop last. Unparseable hops are `None`.
+fn forwarded_for(request: &HttpRequest) -> Vec<Option<IpAddr>> {
+    let headers = request.headers();
+    if headers.contains_key(FORWARDED) {
+        header_values(request, &FORWARDED)
+            .flat_map(|value| value.split(','))
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value.trim().trim_matches('"')))
+            })
+            .collect()
+    } else {
+        header_values(request, &HeaderName::from_static("x-forwarded-for"))
+            .flat_map(|value| value.split(','))
+            .map(|hop| parse_node(hop.trim()))
+            .collect()
+    }
+}
+
+fn header_values<'a>(request: &'a HttpRequest, name: &HeaderName) -> impl Iterator<Item = &'a str> {
+    request
+        .headers()
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+}
+
+/// An address, optionally with a port and IPv6 brackets, e.g. `192.0.2.1`,
+/// `192.0.2.1:4711` or `[2001:db8::1]:4711`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| node.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()
+}
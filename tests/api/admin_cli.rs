@@ -0,0 +1,325 @@
+use std::process::{Output, Stdio};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app};
+
+const NEW_PASSWORD: &str = "correct-horse-battery-staple-42";
+
+/// Run the admin tool against `app`'s database and email server, feeding it
+/// `stdin`.
+async fn admin(app: &TestApp, args: &[&str], stdin: &str) -> Output {
+    let database_name: String = sqlx::query_scalar("SELECT current_database()")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wizard_blog_admin"))
+        .args(args)
+        .env("APP_DATABASE__DATABASE_NAME", database_name)
+        .env("APP_EMAIL_CLIENT__BASE_URL", app.email_server.uri())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run the admin tool");
+    let mut input = child.stdin.take().unwrap();
+    input.write_all(stdin.as_bytes()).await.unwrap();
+    drop(input);
+    child.wait_with_output().await.unwrap()
+}
+
+async fn create_unconfirmed_subscriber(app: &TestApp) {
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn users_can_be_created_from_the_command_line() {
+    let app = spawn_app().await;
+
+    let output = admin(
+        &app,
+        &[
+            "users",
+            "create",
+            "--username",
+            "ursula",
+            "--role",
+            "editor",
+        ],
+        &format!("{}\n", NEW_PASSWORD),
+    )
+    .await;
+
+    assert!(output.status.success(), "{:?}", output);
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula",
+            "password": NEW_PASSWORD
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let event = sqlx::query!("SELECT action, target, user_agent FROM audit_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.action, "user.invited");
+    assert_eq!(event.target.as_deref(), Some("ursula"));
+    assert_eq!(event.user_agent.as_deref(), Some("wizard_blog_admin"));
+}
+
+#[tokio::test]
+async fn passwords_reset_from_the_command_line_replace_the_old_one() {
+    let app = spawn_app().await;
+
+    let output = admin(
+        &app,
+        &[
+            "users",
+            "reset-password",
+            "--username",
+            &app.test_user.username,
+        ],
+        NEW_PASSWORD,
+    )
+    .await;
+
+    assert!(output.status.success(), "{:?}", output);
+    let response = app.login_as(&app.test_user).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": NEW_PASSWORD
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn weak_passwords_are_rejected_by_the_command_line() {
+    let app = spawn_app().await;
+
+    let output = admin(
+        &app,
+        &[
+            "users",
+            "reset-password",
+            "--username",
+            &app.test_user.username,
+        ],
+        "short",
+    )
+    .await;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("The password was rejected"));
+    let response = app.login_as(&app.test_user).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn subscribers_are_dumped_as_json_lines() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let output = admin(&app, &["subscribers", "dump"], "").await;
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(lines[0]["status"], "pending_confirmation");
+    assert!(Uuid::parse_str(lines[0]["id"].as_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn confirmations_can_be_resent_with_a_working_link() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let output = admin(&app, &["subscribers", "resend-confirmations"], "").await;
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Sent 1 confirmation emails.\n"
+    );
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn confirmations_are_resent_to_an_email_regardless_of_case() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let output = admin(
+        &app,
+        &[
+            "subscribers",
+            "resend-confirmations",
+            "--email",
+            "Ursula_Le_Guin@Gmail.com",
+        ],
+        "",
+    )
+    .await;
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Sent 1 confirmation emails.\n"
+    );
+}
+
+#[tokio::test]
+async fn newsletters_can_be_delivered_from_files() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    std::fs::create_dir(&directory).unwrap();
+    let html_file = directory.join("issue.html");
+    let text_file = directory.join("issue.txt");
+    std::fs::write(&html_file, "<p>Newsletter body as HTML</p>").unwrap();
+    std::fs::write(&text_file, "Newsletter body as plain text").unwrap();
+
+    let output = admin(
+        &app,
+        &[
+            "newsletter",
+            "deliver",
+            "--title",
+            "Newsletter title",
+            "--html-file",
+            html_file.to_str().unwrap(),
+            "--text-file",
+            text_file.to_str().unwrap(),
+        ],
+        "",
+    )
+    .await;
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Newsletter title");
+    assert_eq!(body["TextBody"], "Newsletter body as plain text");
+}
+
+#[tokio::test]
+async fn migrations_can_be_run_from_the_command_line() {
+    let app = spawn_app().await;
+
+    let output = admin(&app, &["migrations", "run"], "").await;
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "The database is up to date.\n"
+    );
+}
+
+#[tokio::test]
+async fn migrations_run_from_the_command_line_report_what_they_applied() {
+    let app = spawn_app().await;
+    // Undo the latest migration so there is one to apply
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    for statement in [
+        "DROP INDEX users_lower_email_idx",
+        "ALTER TABLE users ADD CONSTRAINT users_email_key UNIQUE (email)",
+        "DELETE FROM _sqlx_migrations WHERE version = 20261019220000",
+    ] {
+        sqlx::query(statement)
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+    }
+    transaction.commit().await.unwrap();
+
+    let output = admin(&app, &["migrations", "run"], "").await;
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Applied 20261019220000 make user emails case insensitive\n1 migrations applied.\n"
+    );
+}
+
+#[tokio::test]
+async fn a_dry_run_lists_pending_migrations_without_applying_them() {
+    let app = spawn_app().await;
+    let latest: i64 = sqlx::query_scalar(
+        "DELETE FROM _sqlx_migrations \
+        WHERE version = (SELECT MAX(version) FROM _sqlx_migrations) RETURNING version",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    let output = admin(&app, &["migrations", "run", "--dry-run"], "").await;
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with(&format!("Pending {} ", latest)),
+        "{stdout}"
+    );
+    assert!(stdout.ends_with("1 migrations would be applied.\n"));
+    let applied: Option<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE version = $1")
+            .bind(latest)
+            .fetch_optional(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(applied, None);
+}
@@ -0,0 +1,131 @@
+use crate::helpers::{assert_is_redirect_to, extract_between, spawn_app};
+use uuid::Uuid;
+
+async fn is_logged_in(app_address: &str, client: &reqwest::Client) -> bool {
+    let response = client
+        .get(format!("{}/admin/dashboard", app_address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    response.status().as_u16() == 200
+}
+
+#[tokio::test]
+async fn sessions_page_lists_the_users_sessions() {
+    let app = spawn_app().await;
+    app.login_elsewhere(&app.test_user).await;
+    app.login_as(&app.test_user).await;
+
+    let html_page = app.get_admin_sessions_html().await;
+    assert_eq!(html_page.matches("wizard-blog-backend-tests").count(), 2);
+    assert_eq!(html_page.matches("127.0.0.1").count(), 2);
+    assert!(html_page.contains("(this session)"));
+    assert_eq!(html_page.matches("/revoke\"").count(), 1);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_are_not_listed() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    app.login_elsewhere(&editor).await;
+    app.login_as(&app.test_user).await;
+
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(!html_page.contains("/revoke\""));
+}
+
+#[tokio::test]
+async fn revoking_a_session_logs_it_out() {
+    let app = spawn_app().await;
+    let other_client = app.login_elsewhere(&app.test_user).await;
+    app.login_as(&app.test_user).await;
+
+    let html_page = app.get_admin_sessions_html().await;
+    let session_id = extract_between(&html_page, "/admin/sessions/", "/revoke\"");
+    let response = app
+        .post_admin_sessions(&format!("/{session_id}/revoke"))
+        .await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("<p><i>The session has been revoked.</i></p>"));
+    assert!(!is_logged_in(&app.address, &other_client).await);
+    assert!(is_logged_in(&app.address, &app.api_client).await);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_revoked() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    let editor_client = app.login_elsewhere(&editor).await;
+    let session_id: String = sqlx::query_scalar!(
+        "SELECT session_id FROM user_sessions WHERE user_id = $1",
+        editor.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    app.login_as(&app.test_user).await;
+
+    app.post_admin_sessions(&format!("/{session_id}/revoke"))
+        .await;
+
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("<p><i>The session does not exist.</i></p>"));
+    assert!(is_logged_in(&app.address, &editor_client).await);
+}
+
+#[tokio::test]
+async fn revoking_other_sessions_keeps_the_current_one() {
+    let app = spawn_app().await;
+    let first_client = app.login_elsewhere(&app.test_user).await;
+    let second_client = app.login_elsewhere(&app.test_user).await;
+    app.login_as(&app.test_user).await;
+
+    let response = app.post_admin_sessions("/revoke-others").await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("<p><i>All other sessions have been logged out.</i></p>"));
+    assert!(!html_page.contains("/revoke\""));
+    assert!(!is_logged_in(&app.address, &first_client).await);
+    assert!(!is_logged_in(&app.address, &second_client).await);
+    assert!(is_logged_in(&app.address, &app.api_client).await);
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_other_sessions() {
+    let app = spawn_app().await;
+    let other_client = app.login_elsewhere(&app.test_user).await;
+    app.login_as(&app.test_user).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    assert!(!is_logged_in(&app.address, &other_client).await);
+    assert!(is_logged_in(&app.address, &app.api_client).await);
+}
+
+#[tokio::test]
+async fn logging_out_removes_the_session() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+
+    app.post_logout().await;
+
+    let sessions: i64 = sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM user_sessions WHERE user_id = $1"#,
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(sessions, 0);
+}
@@ -0,0 +1,54 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_admin_subscribers().await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscriber_detail_page_shows_the_consent_log() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(
+        "name=Harsh%20Verma&email=harshvse%40gmail.com&source=footer_form".into(),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.post_login(&serde_json::json!({
+    "username": &app.test_user.username,
+    "password": &app.test_user.password
+    }))
+    .await;
+    // Act
+    let html_page = app
+        .get_admin_subscriber(subscriber_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    // Assert
+    assert!(html_page.contains("harshvse@gmail.com"));
+    assert!(html_page.contains("<td>footer_form</td>"));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+}
@@ -0,0 +1,242 @@
+use crate::helpers::{TestUser, assert_is_redirect_to, extract_between, spawn_app};
+
+#[tokio::test]
+async fn owners_can_invite_users_who_can_then_log_in() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .post_admin_users(
+            "",
+            &serde_json::json!({ "username": "new-author", "role": "author" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("new-author has been invited as author."));
+    let password = extract_between(&html_page, "<code>", "</code>");
+    app.post_logout().await;
+
+    let invited = TestUser {
+        user_id: uuid::Uuid::nil(),
+        username: "new-author".into(),
+        password,
+    };
+    let response = app.login_as(&invited).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("Your role: author"));
+}
+
+#[tokio::test]
+async fn duplicate_usernames_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .post_admin_users(
+            "",
+            &serde_json::json!({ "username": &app.test_user.username, "role": "viewer" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users().await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>A user with that username already exists.</i></p>"));
+}
+
+#[tokio::test]
+async fn emails_are_stored_in_lowercase_and_unique_regardless_of_case() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+
+    app.post_admin_users(
+        "",
+        &serde_json::json!({
+            "username": "ursula",
+            "email": "Ursula.Le.Guin@Example.com",
+            "role": "author"
+        }),
+    )
+    .await;
+    let response = app
+        .post_admin_users(
+            "",
+            &serde_json::json!({
+                "username": "ursula-again",
+                "email": "ursula.le.guin@example.com",
+                "role": "author"
+            }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users().await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>Another user already uses that email address.</i></p>"));
+    let emails: Vec<Option<String>> =
+        sqlx::query_scalar("SELECT email FROM users WHERE username LIKE 'ursula%'")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(emails, vec![Some("ursula.le.guin@example.com".to_string())]);
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    app.login_as(&editor).await;
+
+    let response = app.get_admin_users().await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app
+        .post_admin_users(
+            "",
+            &serde_json::json!({ "username": "sneaky", "role": "owner" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn authors_can_draft_but_not_publish_newsletters() {
+    let app = spawn_app().await;
+    let author = app.add_user("author").await;
+    app.login_as(&author).await;
+
+    let html_page = app.get_newsletter_form().await;
+    assert!(html_page.contains("<form"));
+    assert!(!html_page.contains("Publish Newsletter</button>"));
+    let newsletter = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>"
+    });
+    let response = app.post_newsletter_draft(newsletter.clone()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert!(
+        app.get_newsletter_form()
+            .await
+            .contains("<td>Newsletter title</td>")
+    );
+    let response = app.post_newsletter(newsletter).await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn viewers_cannot_draft_newsletters() {
+    let app = spawn_app().await;
+    let viewer = app.add_user("viewer").await;
+    app.login_as(&viewer).await;
+
+    let response = app
+        .post_newsletter_draft(serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn viewers_cannot_see_subscribers() {
+    let app = spawn_app().await;
+    let viewer = app.add_user("viewer").await;
+    app.login_as(&viewer).await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(!html_page.contains("/admin/subscribers"));
+    let response = app.get_admin_subscribers().await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn deactivated_users_are_logged_out_and_cannot_log_in() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+
+    // The editor logs in on another client
+    let other_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    other_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &editor.username,
+            "password": &editor.password
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    app.login_as(&app.test_user).await;
+    let response = app
+        .post_admin_users(
+            &format!("/{}/deactivate", editor.user_id),
+            &serde_json::json!({}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = other_client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+    app.post_logout().await;
+    let response = app.login_as(&editor).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_can_change_roles_and_delete_users() {
+    let app = spawn_app().await;
+    let viewer = app.add_user("viewer").await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .post_admin_users(
+            &format!("/{}/role", viewer.user_id),
+            &serde_json::json!({ "role": "editor" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let role = sqlx::query!("SELECT role FROM users WHERE user_id = $1", viewer.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .role;
+    assert_eq!(role, "editor");
+
+    let response = app
+        .post_admin_users(
+            &format!("/{}/delete", viewer.user_id),
+            &serde_json::json!({}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users().await.text().await.unwrap();
+    assert!(!html_page.contains(&viewer.username));
+}
+
+#[tokio::test]
+async fn owners_cannot_demote_or_delete_themselves() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+
+    for (path, body) in [
+        ("role", serde_json::json!({ "role": "viewer" })),
+        ("deactivate", serde_json::json!({})),
+        ("delete", serde_json::json!({})),
+    ] {
+        let response = app
+            .post_admin_users(&format!("/{}/{}", app.test_user.user_id, path), &body)
+            .await;
+        assert_is_redirect_to(&response, "/admin/users");
+    }
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("Your role: owner"));
+}
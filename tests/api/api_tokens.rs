@@ -0,0 +1,240 @@
+use wiremock::{Mock, ResponseTemplate, matchers::any};
+
+use crate::helpers::{TestApp, assert_is_redirect_to, extract_between, spawn_app};
+
+fn newsletter() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+async fn api_publish(app: &TestApp, token: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/newsletters", &app.address))
+        .bearer_auth(token)
+        .json(&newsletter())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn admin_get(app: &TestApp, path: &str, token: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/admin{}", &app.address, path))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Create a token with `scopes` as the test user, then drop the session so
+/// only the token authenticates further requests.
+async fn token_with_scopes(app: &TestApp, scopes: &[&str]) -> String {
+    app.login_as(&app.test_user).await;
+    let token = app.create_api_token(scopes).await;
+    app.post_logout().await;
+    token
+}
+
+#[tokio::test]
+async fn tokens_can_publish_newsletters_through_the_api() {
+    let app = spawn_app().await;
+    let token = token_with_scopes(&app, &["newsletters:publish"]).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = api_publish(&app, &token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "published");
+}
+
+#[tokio::test]
+async fn draft_tokens_can_save_drafts_but_not_publish() {
+    let app = spawn_app().await;
+    let token = token_with_scopes(&app, &["newsletters:draft"]).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/newsletters/drafts", &app.address))
+        .bearer_auth(&token)
+        .json(&newsletter())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "drafted");
+    assert_eq!(api_publish(&app, &token).await.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn tokens_can_use_admin_routes_without_a_csrf_token() {
+    let app = spawn_app().await;
+    let token = token_with_scopes(&app, &["newsletters:publish"]).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .bearer_auth(&token)
+        .form(&newsletter())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn tokens_are_limited_to_their_scopes() {
+    let app = spawn_app().await;
+    let token = token_with_scopes(&app, &["subscribers:read"]).await;
+
+    assert_eq!(admin_get(&app, "/subscribers", &token).await.status(), 200);
+    assert_eq!(api_publish(&app, &token).await.status().as_u16(), 403);
+    assert_eq!(admin_get(&app, "/users", &token).await.status(), 403);
+}
+
+#[tokio::test]
+async fn tokens_cannot_manage_the_account() {
+    let app = spawn_app().await;
+    let token = token_with_scopes(&app, &["users:manage"]).await;
+
+    for path in [
+        "/dashboard",
+        "/password",
+        "/sessions",
+        "/api-tokens",
+        "/two-factor",
+    ] {
+        let response = admin_get(&app, path, &token).await;
+        assert_eq!(response.status().as_u16(), 403, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn invalid_tokens_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = api_publish(&app, "wbt_not-a-real-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    let response = admin_get(&app, "/subscribers", "wbt_not-a-real-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn anonymous_api_requests_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/newsletters", &app.address))
+        .json(&newsletter())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_tokens_are_rejected() {
+    let app = spawn_app().await;
+    let token = token_with_scopes(&app, &["newsletters:publish"]).await;
+
+    sqlx::query!("UPDATE api_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(api_publish(&app, &token).await.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn tokens_of_deactivated_users_are_rejected() {
+    let app = spawn_app().await;
+    let token = token_with_scopes(&app, &["newsletters:publish"]).await;
+
+    sqlx::query!(
+        "UPDATE users SET is_active = false WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(api_publish(&app, &token).await.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+    let token = app.create_api_token(&["newsletters:publish"]).await;
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/api-tokens", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let token_id = extract_between(&html_page, "/admin/api-tokens/", "/revoke");
+
+    let body = app.with_csrf_token(&serde_json::json!({})).await;
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/api-tokens/{token_id}/revoke",
+            &app.address
+        ))
+        .form(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+
+    assert_eq!(api_publish(&app, &token).await.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn tokens_are_stored_hashed_and_record_their_last_use() {
+    let app = spawn_app().await;
+    let token = token_with_scopes(&app, &["subscribers:read"]).await;
+
+    admin_get(&app, "/subscribers", &token).await;
+
+    let row = sqlx::query!("SELECT token_hash, last_used_at FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(row.token_hash, token);
+    assert!(row.last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn tokens_cannot_have_scopes_beyond_the_users_role() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    app.login_as(&editor).await;
+
+    let response = app
+        .post_admin_api_tokens(&[("name", "ci"), ("scopes", "users:manage")])
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    let count = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM api_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
@@ -0,0 +1,179 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn logins_are_recorded() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password"
+    }))
+    .await;
+    app.login_as(&app.test_user).await;
+
+    let html_page = app.get_admin_audit_html().await;
+    assert!(html_page.contains("<td>login.failed</td>"));
+    assert!(html_page.contains("<td>login.succeeded</td>"));
+    assert!(html_page.contains(&format!("<td>{}</td>", app.test_user.username)));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+    assert!(html_page.contains("<td>wizard-blog-backend-tests</td>"));
+}
+
+#[tokio::test]
+async fn password_changes_are_recorded() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let html_page = app.get_admin_audit_html().await;
+    assert!(html_page.contains("<td>password.changed</td>"));
+}
+
+#[tokio::test]
+async fn published_newsletters_are_recorded_with_their_title() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Spring <issue>",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_admin_audit_html().await;
+    assert!(html_page.contains("<td>newsletter.published</td>"));
+    assert!(html_page.contains("<td>Spring &lt;issue&gt;</td>"));
+}
+
+#[tokio::test]
+async fn events_can_be_filtered_by_action_and_actor() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    app.login_elsewhere(&editor).await;
+    app.login_as(&app.test_user).await;
+    app.post_logout().await;
+    app.login_as(&app.test_user).await;
+
+    let html_page = app
+        .get_admin_audit("?action=login.succeeded&actor=")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(html_page.matches("<td>login.succeeded</td>").count(), 3);
+    assert!(!html_page.contains("<td>logout</td>"));
+
+    let html_page = app
+        .get_admin_audit(&format!("?action=&actor={}", editor.username))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(html_page.matches("<td>login.succeeded</td>").count(), 1);
+    assert!(!html_page.contains(&format!("<td>{}</td>", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn events_can_be_filtered_by_date() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+    let today = chrono::Utc::now().date_naive();
+
+    let html_page = app
+        .get_admin_audit(&format!("?from={today}&to={today}"))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<td>login.succeeded</td>"));
+
+    let yesterday = today.pred_opt().unwrap();
+    let html_page = app
+        .get_admin_audit(&format!("?to={yesterday}"))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(!html_page.contains("<td>login.succeeded</td>"));
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+
+    for query in ["?action=everything", "?from=yesterday"] {
+        let response = app.get_admin_audit(query).await;
+        assert_eq!(response.status().as_u16(), 400, "{query}");
+    }
+}
+
+#[tokio::test]
+async fn the_export_is_a_json_attachment_honouring_the_filters() {
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+    app.post_logout().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app.get_admin_audit("/export?action=logout").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let disposition = response.headers()["Content-Disposition"].to_str().unwrap();
+    assert!(disposition.starts_with("attachment"));
+    let events: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["action"], "logout");
+    assert_eq!(events[0]["actor_id"], app.test_user.user_id.to_string());
+    assert_eq!(events[0]["actor_username"], app.test_user.username.as_str());
+    assert_eq!(events[0]["ip_address"], "127.0.0.1");
+}
+
+#[tokio::test]
+async fn events_outlive_the_actors_account() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    app.login_elsewhere(&editor).await;
+    app.login_as(&app.test_user).await;
+    let response = app
+        .post_admin_users(
+            &format!("/{}/delete", editor.user_id),
+            &serde_json::json!({}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = app
+        .get_admin_audit(&format!("/export?actor={}", editor.username))
+        .await;
+    let events: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["action"], "login.succeeded");
+    assert!(events[0]["actor_id"].is_null());
+
+    let html_page = app.get_admin_audit_html().await;
+    assert!(html_page.contains("<td>user.deleted</td>"));
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_log() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    app.login_as(&editor).await;
+
+    assert_eq!(app.get_admin_audit("").await.status().as_u16(), 403);
+    assert_eq!(app.get_admin_audit("/export").await.status().as_u16(), 403);
+    assert!(
+        !app.get_admin_dashboard_html()
+            .await
+            .contains("/admin/audit")
+    );
+}
@@ -1,4 +1,5 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use sha1::Digest;
 use uuid::Uuid;
 #[tokio::test]
 async fn you_must_be_logged_in_to_see_the_change_password_form() {
@@ -146,3 +147,75 @@ async fn changing_password_works() {
     let response = app.post_login(&login_body).await;
     assert_is_redirect_to(&response, "/admin/dashboard");
 }
+
+#[tokio::test]
+async fn each_password_policy_violation_is_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": "password",
+        "new_password_check": "password",
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>The password must be at least 12 characters long.</i></p>"));
+    assert!(html_page.contains("<p><i>The password is too easy to guess"));
+}
+
+#[tokio::test]
+async fn new_password_must_differ_from_the_current_one() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_as(&app.test_user).await;
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &app.test_user.password,
+        "new_password_check": &app.test_user.password,
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(
+        html_page
+            .contains("<p><i>The new password must be different from the current one.</i></p>")
+    );
+}
+
+#[tokio::test]
+async fn breached_passwords_are_rejected() {
+    // Arrange
+    let breached_password = Uuid::new_v4().to_string();
+    let hash = hex::encode_upper(sha1::Sha1::digest(breached_password.as_bytes()));
+    let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join(&hash[..5]), format!("{}:42\r\n", &hash[5..])).unwrap();
+    let app = spawn_app_with(|c| {
+        c.password_policy.breached_passwords_directory = Some(directory.clone())
+    })
+    .await;
+    app.login_as(&app.test_user).await;
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &breached_password,
+        "new_password_check": &breached_password,
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains(
+        "<p><i>The password has appeared in a data breach - please choose a different one.</i></p>"
+    ));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,50 @@
+use std::process::Command;
+use uuid::Uuid;
+
+fn check_config(envs: &[(&str, &str)]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_wizard_blog_backend"))
+        .arg("--check-config")
+        .envs(envs.iter().copied())
+        .output()
+        .expect("Failed to run the application")
+}
+
+#[test]
+fn check_config_accepts_the_local_configuration() {
+    let output = check_config(&[]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "The configuration is valid.\n"
+    );
+}
+
+#[test]
+fn check_config_lists_every_problem_and_fails() {
+    let output = check_config(&[
+        ("APP_HMAC_SECRET", "short"),
+        ("APP_EMAIL_CLIENT__SENDER_EMAIL", "not-an-email"),
+    ]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("hmac_secret must be at least 32 characters long"));
+    assert!(stderr.contains("email_client.sender_email"));
+}
+
+#[test]
+fn secrets_are_read_from_files() {
+    let path = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    std::fs::write(&path, "short\n").unwrap();
+
+    let output = check_config(&[("APP_HMAC_SECRET_FILE", path.to_str().unwrap())]);
+    std::fs::remove_file(&path).unwrap();
+
+    // The file's content replaced the valid secret of local.yaml
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("hmac_secret must be at least 32 characters long")
+    );
+}
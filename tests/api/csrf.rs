@@ -0,0 +1,85 @@
+use crate::helpers::{TestApp, spawn_app};
+
+async fn login(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+    "username": &app.test_user.username,
+    "password": &app.test_user.password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn admin_forms_without_a_csrf_token_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .form(&serde_json::json!({
+            "title": "newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as Html</p>",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn admin_forms_with_a_wrong_csrf_token_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .form(&serde_json::json!({ "csrf_token": "not-the-right-token" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn the_csrf_token_is_accepted_as_a_header() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let csrf_token = app.get_csrf_token().await.unwrap();
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .header("X-CSRF-Token", csrf_token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+}
+
+#[tokio::test]
+async fn the_session_cookie_is_same_site_strict() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = login(&app).await;
+    // Assert
+    let session_cookie = response
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .map(|h| h.to_str().unwrap())
+        .find(|c| c.starts_with("id="))
+        .expect("No session cookie was set");
+    assert!(session_cookie.contains("SameSite=Strict"));
+    assert!(session_cookie.contains("HttpOnly"));
+}
@@ -0,0 +1,73 @@
+use wizard_blog_backend::configuration::{ReadReplicaSettings, Settings};
+
+use crate::helpers::spawn_app_with;
+
+/// Nothing listens on the discard port.
+fn unreachable_replica(c: &mut Settings) {
+    c.database.read_replica = Some(ReadReplicaSettings {
+        host: "127.0.0.1".into(),
+        port: 9,
+    });
+    c.database.acquire_timeout_milliseconds = 500;
+}
+
+#[tokio::test]
+async fn the_pool_size_comes_from_the_configuration() {
+    let app = spawn_app_with(|c| c.database.max_connections = 3).await;
+
+    let body = app.get_metrics().await.text().await.unwrap();
+
+    assert!(body.contains("db_pool_max_connections 3"), "{body}");
+}
+
+#[tokio::test]
+async fn slow_statements_are_cancelled() {
+    let app = spawn_app_with(|c| c.database.statement_timeout_milliseconds = Some(100)).await;
+
+    let error = sqlx::query("SELECT pg_sleep(1)")
+        .execute(&app.db_pool)
+        .await
+        .unwrap_err();
+
+    assert!(error.to_string().contains("statement timeout"), "{error}");
+}
+
+#[tokio::test]
+async fn read_only_pages_are_served_by_the_replica() {
+    let app = spawn_app_with(|c| {
+        // The primary itself stands in for a replica
+        c.database.read_replica = Some(ReadReplicaSettings {
+            host: c.database.host.clone(),
+            port: c.database.port,
+        });
+    })
+    .await;
+    app.login_as(&app.test_user).await;
+
+    let response = app.get_admin_subscribers().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_unreachable_replica_only_fails_read_only_pages() {
+    let app = spawn_app_with(unreachable_replica).await;
+    app.login_as(&app.test_user).await;
+
+    assert_eq!(app.get_admin_subscribers().await.status().as_u16(), 500);
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_unreachable_replica_makes_the_app_unready() {
+    let app = spawn_app_with(unreachable_replica).await;
+
+    let response = reqwest::get(format!("{}/health/ready", &app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["postgres_replica"]["status"], "down");
+    assert_eq!(body["checks"]["postgres"]["status"], "up");
+}
@@ -0,0 +1,133 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn unknown_pages_render_a_branded_404_page() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/no-such-page", &app.address))
+        .header("X-Request-Id", "edge-404")
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(
+        response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>404 Not Found</h1>"));
+    assert!(html_page.contains("This page does not exist."));
+    assert!(html_page.contains("<code>edge-404</code>"));
+}
+
+#[tokio::test]
+async fn clients_accepting_json_get_problem_details() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .header("X-Request-Id", "edge-json")
+        .body("name=le%20guin&email=definitely-not-an-email")
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["request_id"], "edge-json");
+    assert!(
+        problem["detail"]
+            .as_str()
+            .unwrap()
+            .contains("is an invalid email")
+    );
+}
+
+#[tokio::test]
+async fn html_is_preferred_when_the_client_ranks_it_first() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/no-such-page", &app.address))
+        .header("Accept", "text/html,application/json;q=0.9")
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(
+        response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+}
+
+#[tokio::test]
+async fn server_errors_do_not_leak_their_cause() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    sqlx::query("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Something went wrong on our side."));
+    assert!(!html_page.contains("Caused by"));
+    assert!(!html_page.contains("subscription_token"));
+}
+
+#[tokio::test]
+async fn error_pages_keep_the_headers_of_the_error() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/newsletters", &app.address))
+        .header("Authorization", "Bearer not-a-real-token")
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+        }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("<h1>401 Unauthorized</h1>")
+    );
+}
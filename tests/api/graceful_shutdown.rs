@@ -0,0 +1,116 @@
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wizard_blog_backend::configuration::get_configuration;
+
+use crate::helpers::configure_database;
+
+/// Start the real binary, so that it receives signals like it would in
+/// production, and return it with the port it listens on.
+async fn spawn_binary(email_server: &MockServer) -> (Child, u16) {
+    let mut configuration = get_configuration().expect("Failed to get configuration");
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    configure_database(&configuration.database).await;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wizard_blog_backend"))
+        .env(
+            "APP_DATABASE__DATABASE_NAME",
+            &configuration.database.database_name,
+        )
+        .env("APP_APPLICATION_PORT", "0")
+        .env("APP_EMAIL_CLIENT__BASE_URL", email_server.uri())
+        .env("APP_REDIS_KEY_PREFIX", Uuid::new_v4().to_string())
+        .env("APP_SUBSCRIPTIONS__MIN_FORM_FILL_MILLISECONDS", "0")
+        .env("APP_SHUTDOWN_TIMEOUT_SECONDS", "10")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("Failed to start the application");
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let port = loop {
+        let line = lines
+            .next_line()
+            .await
+            .unwrap()
+            .expect("The application exited before listening");
+        if let Some(port) = line.strip_prefix("starting server on port: ") {
+            break port.trim().parse().unwrap();
+        }
+    };
+    // Keep draining the logs so the application never blocks on a full pipe
+    tokio::spawn(async move { while let Ok(Some(_)) = lines.next_line().await {} });
+    (child, port)
+}
+
+fn send_sigterm(child: &Child) {
+    let status = std::process::Command::new("kill")
+        .args(["-TERM", &child.id().unwrap().to_string()])
+        .status()
+        .expect("Failed to run kill");
+    assert!(status.success());
+}
+
+#[tokio::test]
+async fn sigterm_lets_in_flight_requests_finish_and_exits_cleanly() {
+    // Arrange
+    let email_server = MockServer::start().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .expect(1)
+        .mount(&email_server)
+        .await;
+    let (mut child, port) = spawn_binary(&email_server).await;
+    let address = format!("http://127.0.0.1:{}", port);
+
+    // Act - Part 1 - Start a request that is still waiting on the email
+    // provider when the signal arrives
+    let in_flight = tokio::spawn({
+        let address = address.clone();
+        async move {
+            reqwest::Client::new()
+                .post(format!("{}/subscriptions", address))
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+                .send()
+                .await
+        }
+    });
+    // Polling rather than sleeping, as the request may take a while to get
+    // there when the machine is busy
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while email_server.received_requests().await.unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("The subscription request never reached the email provider");
+    send_sigterm(&child);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Act - Part 2 - New connections are turned away while draining
+    let refused = reqwest::Client::new()
+        .get(format!("{}/health/live", address))
+        .timeout(Duration::from_secs(1))
+        .send()
+        .await;
+    assert!(refused.is_err());
+
+    // Assert
+    let response = in_flight
+        .await
+        .unwrap()
+        .expect("The in-flight request failed");
+    assert_eq!(response.status().as_u16(), 200);
+    let status = tokio::time::timeout(Duration::from_secs(15), child.wait())
+        .await
+        .expect("The application did not exit after SIGTERM")
+        .unwrap();
+    assert!(status.success());
+}
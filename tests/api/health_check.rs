@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -6,7 +6,7 @@ async fn health_check_works() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/health", test_app.address))
+        .get(format!("{}/health", test_app.address))
         .send()
         .await
         .expect("failed to execute request.");
@@ -14,3 +14,106 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[tokio::test]
+async fn liveness_does_not_depend_on_anything() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/health/live", app.address))
+        .send()
+        .await
+        .expect("failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "live");
+}
+
+#[tokio::test]
+async fn readiness_reports_every_dependency() {
+    let app = spawn_app().await;
+
+    let response = get_readiness(&app.address).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ready");
+    for dependency in ["postgres", "redis", "migrations"] {
+        assert_eq!(body["checks"][dependency]["status"], "up", "{dependency}");
+        assert!(body["checks"][dependency]["latency_ms"].is_u64());
+    }
+    assert!(body["checks"]["email_provider"].is_null());
+}
+
+#[tokio::test]
+async fn pending_migrations_make_the_app_unready() {
+    let app = spawn_app().await;
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = get_readiness(&app.address).await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "unavailable");
+    assert_eq!(body["checks"]["migrations"]["status"], "down");
+    assert_eq!(
+        body["checks"]["migrations"]["detail"],
+        "1 pending migration(s)"
+    );
+    assert_eq!(body["checks"]["postgres"]["status"], "up");
+}
+
+#[tokio::test]
+async fn the_email_provider_is_checked_when_enabled() {
+    let app = spawn_app_with(|c| c.health.check_email_provider = true).await;
+
+    let response = get_readiness(&app.address).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["email_provider"]["status"], "up");
+}
+
+#[tokio::test]
+async fn an_unreachable_email_provider_makes_the_app_unready() {
+    let app = spawn_app_with(|c| {
+        c.health.check_email_provider = true;
+        // Nothing listens on the discard port
+        c.email_client.base_url = "http://127.0.0.1:9".into();
+    })
+    .await;
+
+    let response = get_readiness(&app.address).await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["email_provider"]["status"], "down");
+}
+
+#[tokio::test]
+async fn readiness_reports_are_cached_briefly() {
+    let app = spawn_app_with(|c| c.health.cache_milliseconds = 60_000).await;
+    assert_eq!(get_readiness(&app.address).await.status().as_u16(), 200);
+
+    sqlx::query("DELETE FROM _sqlx_migrations")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(get_readiness(&app.address).await.status().as_u16(), 200);
+}
+
+async fn get_readiness(address: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/health/ready", address))
+        .send()
+        .await
+        .expect("failed to execute request.")
+}
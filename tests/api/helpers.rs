@@ -2,22 +2,36 @@ use argon2::password_hash::SaltString;
 use argon2::password_hash::rand_core::OsRng;
 use argon2::{Argon2, Params, PasswordHasher};
 use once_cell::sync::Lazy;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use wiremock::MockServer;
-use wizard_blog_backend::configuration::{DatabaseSettings, get_configuration};
+use wizard_blog_backend::configuration::{DatabaseSettings, Settings, get_configuration};
 use wizard_blog_backend::startup::{Application, get_connection_pool};
-use wizard_blog_backend::telemetry::{get_subscriber, init_subscriber};
+use wizard_blog_backend::telemetry::{get_subscriber, get_tracer, init_subscriber};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
     let subscriber_name = "test".to_string();
+    // Nothing is exported, but spans get real trace contexts to propagate
+    let tracer = get_tracer(&SdkTracerProvider::builder().build());
 
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            Some(tracer),
+        );
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::sink,
+            Some(tracer),
+        );
         init_subscriber(subscriber);
     }
 });
@@ -25,6 +39,9 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 pub struct TestApp {
     pub address: String,
     pub port: u16,
+    pub metrics_port: Option<u16>,
+    /// Sent along by `get_metrics`.
+    pub metrics_token: Option<String>,
     pub db_pool: PgPool,
     pub email_server: MockServer,
     pub api_client: reqwest::Client,
@@ -45,7 +62,7 @@ pub struct ConfirmationLinks {
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -53,9 +70,44 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn get_home_html(&self) -> String {
+        self.api_client
+            .get(&self.address)
+            .send()
+            .await
+            .expect("failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
     pub async fn post_newsletter(&self, body: serde_json::Value) -> reqwest::Response {
+        let body = self.with_csrf_token(&body).await;
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_newsletter_draft(&self, body: serde_json::Value) -> reqwest::Response {
+        let body = self.with_csrf_token(&body).await;
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn publish_newsletter_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        let body = self.with_csrf_token(&serde_json::json!({})).await;
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/publish",
+                &self.address, draft_id
+            ))
             .form(&body)
             .send()
             .await
@@ -67,7 +119,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -76,7 +128,7 @@ impl TestApp {
 
     pub async fn get_login_form(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request ")
@@ -86,7 +138,7 @@ impl TestApp {
     }
     pub async fn get_newsletter_form(&self) -> String {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request ")
@@ -97,7 +149,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -106,6 +158,28 @@ impl TestApp {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
 
+    /// The CSRF token embedded in the admin forms for the current session, if
+    /// the client is logged in.
+    pub async fn get_csrf_token(&self) -> Option<String> {
+        let html_page = self.get_admin_dashboard_html().await;
+        let marker = r#"name="csrf_token" value=""#;
+        let start = html_page.find(marker)? + marker.len();
+        let end = start + html_page[start..].find('"')?;
+        Some(html_page[start..end].to_string())
+    }
+
+    /// Add the session's CSRF token to a form body.
+    pub async fn with_csrf_token<Body>(&self, body: &Body) -> serde_json::Value
+    where
+        Body: serde::Serialize,
+    {
+        let mut body = serde_json::to_value(body).unwrap();
+        if let Some(token) = self.get_csrf_token().await {
+            body["csrf_token"] = token.into();
+        }
+        body
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -124,14 +198,14 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
 
         ConfirmationLinks { html, plain_text }
     }
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -141,9 +215,10 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
+        let body = self.with_csrf_token(body).await;
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
-            .form(body)
+            .post(format!("{}/admin/password", &self.address))
+            .form(&body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -153,9 +228,243 @@ impl TestApp {
         self.get_change_password().await.text().await.unwrap()
     }
 
+    pub async fn get_admin_subscribers(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_two_factor_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Post to one of the two-factor settings forms, using the CSRF token
+    /// embedded in that page since the dashboard may be off limits.
+    pub async fn post_admin_two_factor(&self, path: &str, code: &str) -> reqwest::Response {
+        let html_page = self.get_admin_two_factor_html().await;
+        let csrf_token = extract_between(&html_page, r#"name="csrf_token" value=""#, "\"");
+        self.api_client
+            .post(format!("{}{}", &self.address, path))
+            .form(&serde_json::json!({ "code": code, "csrf_token": csrf_token }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_two_factor(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/login/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/two-factor", &self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Store a new user with `role` next to the test user, who is an owner.
+    pub async fn add_user(&self, role: &str) -> TestUser {
+        let user = TestUser::generate();
+        user.store(&self.db_pool, role).await;
+        user
+    }
+
+    pub async fn login_as(&self, user: &TestUser) -> reqwest::Response {
+        self.post_login(&serde_json::json!({
+            "username": &user.username,
+            "password": &user.password
+        }))
+        .await
+    }
+
+    /// Log `user` in from another browser, returning its client.
+    pub async fn login_elsewhere(&self, user: &TestUser) -> reqwest::Client {
+        let client = new_api_client();
+        let response = client
+            .post(format!("{}/login", &self.address))
+            .form(&serde_json::json!({
+                "username": &user.username,
+                "password": &user.password
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_is_redirect_to(&response, "/admin/dashboard");
+        client
+    }
+
+    pub async fn get_admin_sessions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_sessions(&self, path: &str) -> reqwest::Response {
+        let body = self.with_csrf_token(&serde_json::json!({})).await;
+        self.api_client
+            .post(format!("{}/admin/sessions{}", &self.address, path))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Submit the API token creation form, which repeats the `scopes` field.
+    pub async fn post_admin_api_tokens(&self, fields: &[(&str, &str)]) -> reqwest::Response {
+        let mut body: Vec<(String, String)> = fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        if let Some(token) = self.get_csrf_token().await {
+            body.push(("csrf_token".into(), token));
+        }
+        self.api_client
+            .post(format!("{}/admin/api-tokens", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Create an API token with `scopes` for the logged-in user.
+    pub async fn create_api_token(&self, scopes: &[&str]) -> String {
+        let mut fields = vec![("name", "ci"), ("expires_in_days", "30")];
+        fields.extend(scopes.iter().map(|scope| ("scopes", *scope)));
+        let response = self.post_admin_api_tokens(&fields).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let html_page = response.text().await.unwrap();
+        extract_between(&html_page, "header: <code>", "</code>")
+    }
+
+    pub async fn get_admin_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_users<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let body = self.with_csrf_token(body).await;
+        self.api_client
+            .post(format!("{}/admin/users{}", &self.address, path))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_setup(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/setup", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_setup<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/setup", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_forgot_password(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/forgot", &self.address))
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_forgot_password_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login/forgot", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/reset", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_audit(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_audit_html(&self) -> String {
+        self.get_admin_audit("").await.text().await.unwrap()
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        let mut request = self.api_client.get(format!("{}/metrics", &self.address));
+        if let Some(token) = &self.metrics_token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
+        let body = self.with_csrf_token(&serde_json::json!({})).await;
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
+            .form(&body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -171,7 +480,7 @@ impl TestUser {
         }
     }
 
-    async fn store(&self, pool: &PgPool) {
+    async fn store(&self, pool: &PgPool, role: &str) {
         let salt = SaltString::generate(&mut OsRng);
         let password_hash = Argon2::new(
             argon2::Algorithm::Argon2id,
@@ -184,12 +493,13 @@ impl TestUser {
 
         sqlx::query!(
             r#"
-            INSERT INTO users (user_id, username,password_hash)
-            VALUES ($1, $2, $3)
+            INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)
             "#,
             self.user_id,
             self.username,
             password_hash,
+            role,
         )
         .execute(pool)
         .await
@@ -198,6 +508,11 @@ impl TestUser {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after letting the test tweak its configuration.
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
     // Called once and skipped for rest of the calls
     Lazy::force(&TRACING);
 
@@ -208,9 +523,18 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application_port = 0;
         c.email_client.base_url = email_server.uri();
+        // Keep Redis state of concurrently running tests apart
+        c.redis_key_prefix = Uuid::new_v4().to_string();
+        c.subscriptions.min_form_fill_milliseconds = 0;
+        customise(&mut c);
         c
     };
-    configure_database(&configuration.database).await;
+    if configuration.database.run_migrations {
+        // Left to the application
+        create_database(&configuration.database).await;
+    } else {
+        configure_database(&configuration.database).await;
+    }
 
     let application = Application::build(configuration.clone())
         .await
@@ -218,28 +542,38 @@ pub async fn spawn_app() -> TestApp {
 
     let address = format!("http://127.0.0.1:{}", application.port());
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
-
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    let metrics_port = application.metrics_port();
+    tokio::spawn(application.run_until_stopped());
 
     let test_app = TestApp {
         address,
         port: application_port,
+        metrics_port,
+        metrics_token: configuration
+            .metrics
+            .bearer_token
+            .as_ref()
+            .map(|token| token.expose_secret().clone()),
         db_pool: get_connection_pool(&configuration.database),
         email_server,
-        api_client: client,
+        api_client: new_api_client(),
         test_user: TestUser::generate(),
     };
 
-    test_app.test_user.store(&test_app.db_pool).await;
+    test_app.test_user.store(&test_app.db_pool, "owner").await;
     test_app
 }
 
-pub async fn configure_database(config: &DatabaseSettings) {
+fn new_api_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent("wizard-blog-backend-tests")
+        .build()
+        .unwrap()
+}
+
+pub async fn create_database(config: &DatabaseSettings) {
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
         .expect("failed to connect to db");
@@ -248,6 +582,10 @@ pub async fn configure_database(config: &DatabaseSettings) {
         .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
         .await
         .expect("failed to create new database");
+}
+
+pub async fn configure_database(config: &DatabaseSettings) {
+    create_database(config).await;
 
     let connection_pool = PgPool::connect_with(config.with_db())
         .await
@@ -259,6 +597,16 @@ pub async fn configure_database(config: &DatabaseSettings) {
         .expect(" failed to migrate the db");
 }
 
+/// The text between `start` and the following `end` in `haystack`, or an
+/// empty string if `start` is missing.
+pub fn extract_between(haystack: &str, start: &str, end: &str) -> String {
+    let Some(position) = haystack.find(start) else {
+        return String::new();
+    };
+    let rest = &haystack[position + start.len()..];
+    rest[..rest.find(end).unwrap_or(rest.len())].to_string()
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
@@ -1,4 +1,4 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -38,3 +38,128 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     println!("{}", html_page);
     assert!(html_page.contains(&format!("Welcome {}!", app.test_user.username)));
 }
+
+#[tokio::test]
+async fn username_is_locked_out_after_too_many_failed_attempts() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.login.max_failures_per_username = 2;
+        c.login.failure_delay_milliseconds = 0;
+    })
+    .await;
+    let wrong_login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    });
+    // Act - Part 1 - Exhaust the allowed failures
+    for _ in 0..2 {
+        let response = app.post_login(&wrong_login_body).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+    let login_page = app.get_login_form().await;
+    assert!(
+        login_page
+            .contains("<p><i>Too many failed login attempts. Try again in 15 minutes.</i></p>")
+    );
+    // Act - Part 2 - The right password is refused while locked out
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let login_page = app.get_login_form().await;
+    assert!(login_page.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn ip_is_locked_out_after_too_many_failed_attempts() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.login.max_failures_per_ip = 3;
+        c.login.failure_delay_milliseconds = 0;
+    })
+    .await;
+    // Act - Part 1 - Guess passwords for a range of usernames
+    for i in 0..3 {
+        app.post_login(&serde_json::json!({
+            "username": format!("random-username-{}", i),
+            "password": "random-password",
+        }))
+        .await;
+    }
+    // Act - Part 2 - Even a valid account is refused from that IP
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let login_page = app.get_login_form().await;
+    assert!(login_page.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failure_count() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.login.max_failures_per_username = 2;
+        c.login.failure_delay_milliseconds = 0;
+    })
+    .await;
+    let wrong_login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    });
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    });
+    // Act
+    app.post_login(&wrong_login_body).await;
+    assert_is_redirect_to(&app.post_login(&login_body).await, "/admin/dashboard");
+    app.post_login(&wrong_login_body).await;
+    let response = app.post_login(&login_body).await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn outdated_password_hashes_are_upgraded_after_login() {
+    // The test user is stored with m=15000, below the configured cost
+    let app = spawn_app_with(|c| c.password_hashing.memory_cost_kib = 19456).await;
+    let stored_hash = || async {
+        sqlx::query!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            app.test_user.user_id
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .password_hash
+    };
+    assert!(stored_hash().await.contains("m=15000"));
+
+    let response = app.login_as(&app.test_user).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // The rehash happens in the background
+    let mut upgraded = false;
+    for _ in 0..50 {
+        if stored_hash().await.contains("m=19456,t=2,p=1") {
+            upgraded = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert!(upgraded);
+
+    // The upgraded hash still matches the password
+    app.post_logout().await;
+    let response = app.login_as(&app.test_user).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -1,9 +1,29 @@
+mod admin_cli;
+mod admin_sessions;
+mod admin_subscribers;
+mod admin_users;
+mod api_tokens;
+mod audit;
 mod change_password;
+mod check_config;
+mod csrf;
 mod dashboard;
+mod database;
+mod error_pages;
+mod graceful_shutdown;
 mod health_check;
 mod helpers;
 mod login;
+mod metrics;
+mod migrations;
 mod newsletter;
+mod oidc;
+mod password_reset;
 mod publish_newsletter;
+mod request_id;
+mod session_lifetime;
+mod setup;
 mod subscriptions;
 mod subscriptions_confirm;
+mod trace_propagation;
+mod two_factor;
@@ -0,0 +1,152 @@
+use secrecy::Secret;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn metrics_are_exposed_in_the_prometheus_text_format() {
+    let app = spawn_app().await;
+    app.api_client
+        .get(format!("{}/health", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    let response = app.get_metrics().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let content_type = response.headers()["Content-Type"].to_str().unwrap();
+    assert!(content_type.starts_with("text/plain; version=0.0.4"));
+    let body = response.text().await.unwrap();
+    assert!(body.contains(r#"http_requests_total{method="GET",route="/health",status="200"} 1"#));
+    assert!(
+        body.contains(r#"http_request_duration_seconds_count{method="GET",route="/health"} 1"#)
+    );
+    assert!(body.contains(r#"db_pool_connections{state="in_use"}"#));
+    assert!(body.contains("db_pool_max_connections"));
+}
+
+#[tokio::test]
+async fn requests_are_labelled_with_their_route_pattern() {
+    let app = spawn_app().await;
+    for subscriber_id in [uuid::Uuid::new_v4(), uuid::Uuid::new_v4()] {
+        app.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &app.address, subscriber_id
+            ))
+            .send()
+            .await
+            .unwrap();
+    }
+    app.api_client
+        .get(format!("{}/does-not-exist", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    let body = app.get_metrics().await.text().await.unwrap();
+    assert!(body.contains(
+        r#"http_requests_total{method="GET",route="/admin/subscribers/{subscriber_id}",status="303"} 2"#
+    ));
+    assert!(body.contains(r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#));
+}
+
+#[tokio::test]
+async fn subscriber_counts_and_email_deliveries_are_exposed() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    let body = app.get_metrics().await.text().await.unwrap();
+    assert!(body.contains(r#"subscribers{status="pending_confirmation"} 1"#));
+    assert!(body.contains(r#"email_sends_total{outcome="success"} 1"#));
+    assert!(body.contains("email_send_duration_seconds_count 1"));
+}
+
+#[tokio::test]
+async fn failed_email_deliveries_are_counted() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    let body = app.get_metrics().await.text().await.unwrap();
+    assert!(body.contains(r#"email_sends_total{outcome="failure"} 1"#));
+}
+
+#[tokio::test]
+async fn a_configured_bearer_token_is_required() {
+    let app = spawn_app_with(|c| {
+        c.metrics.bearer_token = Some(Secret::new("scrape-me".into()));
+    })
+    .await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+
+    let response = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .bearer_auth("wrong")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .bearer_auth("scrape-me")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn metrics_can_be_served_on_a_separate_port() {
+    let app = spawn_app_with(|c| {
+        c.metrics.port = Some(0);
+        c.metrics.bearer_token = None;
+    })
+    .await;
+
+    assert_eq!(app.get_metrics().await.status().as_u16(), 404);
+
+    let metrics_port = app.metrics_port.expect("The metrics port is not bound");
+    let response = app
+        .api_client
+        .get(format!("http://127.0.0.1:{}/metrics", metrics_port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains(r#"route="unmatched",status="404""#));
+}
+
+#[tokio::test]
+async fn metrics_are_not_served_without_a_token_or_a_port() {
+    let app = spawn_app_with(|c| c.metrics.bearer_token = None).await;
+
+    let response = app.get_metrics().await;
+
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(app.metrics_port, None);
+}
@@ -0,0 +1,84 @@
+use uuid::Uuid;
+use wizard_blog_backend::configuration::{Settings, get_configuration};
+use wizard_blog_backend::migrations::MIGRATOR;
+use wizard_blog_backend::startup::Application;
+
+use crate::helpers::{create_database, spawn_app, spawn_app_with};
+
+/// A configuration pointing at a fresh, empty database.
+async fn configuration_with_empty_database() -> Settings {
+    let mut configuration = get_configuration().expect("Failed to get configuration");
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    configuration.database.run_migrations = true;
+    configuration.application_port = 0;
+    create_database(&configuration.database).await;
+    configuration
+}
+
+async fn applied_migrations(pool: &sqlx::PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations WHERE success")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn migrations_are_applied_at_startup_when_enabled() {
+    let app = spawn_app_with(|c| c.database.run_migrations = true).await;
+
+    assert_eq!(
+        applied_migrations(&app.db_pool).await,
+        MIGRATOR.iter().count() as i64
+    );
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+}
+
+#[tokio::test]
+async fn replicas_starting_together_do_not_race_on_migrations() {
+    let configuration = configuration_with_empty_database().await;
+
+    let (first, second) = tokio::join!(
+        Application::build(configuration.clone()),
+        Application::build(configuration.clone())
+    );
+
+    first.expect("The first replica failed to start");
+    second.expect("The second replica failed to start");
+}
+
+#[tokio::test]
+async fn the_app_refuses_to_start_when_the_database_is_ahead() {
+    let app = spawn_app().await;
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) \
+        VALUES (99991231000000, 'from the future', true, '\\x00', 0)",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let database_name: String = sqlx::query_scalar("SELECT current_database()")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let mut configuration = get_configuration().expect("Failed to get configuration");
+    configuration.database.database_name = database_name;
+    configuration.application_port = 0;
+
+    let error = Application::build(configuration)
+        .await
+        .err()
+        .expect("The application started against a newer database");
+
+    assert!(
+        error
+            .to_string()
+            .contains("does not know about (99991231000000)"),
+        "{error}"
+    );
+}
@@ -118,6 +118,82 @@ async fn requests_missing_authorization_are_rejected() {
     assert_is_redirect_to(&response, "/login");
 }
 
+#[tokio::test]
+async fn editors_publish_drafts_saved_by_authors() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let author = app.add_user("author").await;
+    app.login_as(&author).await;
+    app.post_newsletter_draft(serde_json::json!({
+        "title": "Drafted title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as Html</p>",
+    }))
+    .await;
+    app.post_logout().await;
+    let draft_id = sqlx::query!("SELECT draft_id FROM newsletter_drafts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .draft_id;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let editor = app.add_user("editor").await;
+    app.login_as(&editor).await;
+
+    // Act
+    let response = app.publish_newsletter_draft(draft_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_form().await;
+    assert!(html_page.contains("The newsletter issue has been published!"));
+    assert!(!html_page.contains("Drafted title"));
+    // A published draft cannot be delivered a second time
+    let response = app.publish_newsletter_draft(draft_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn drafts_stay_unpublished_when_delivery_fails() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_as(&app.test_user).await;
+    app.post_newsletter_draft(serde_json::json!({
+        "title": "Drafted title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as Html</p>",
+    }))
+    .await;
+    let draft_id = sqlx::query!("SELECT draft_id FROM newsletter_drafts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .draft_id;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.publish_newsletter_draft(draft_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let saved = sqlx::query!("SELECT published_at FROM newsletter_drafts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.published_at.is_none());
+}
+
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let body = "name=Harsh%20Verma&email=harshvse%40gmail.com";
     let _mock_guard = Mock::given(path("/email"))
@@ -140,7 +216,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .pop()
         .unwrap();
 
-    app.get_confirmation_links(&email_request)
+    app.get_confirmation_links(email_request)
 }
 
 async fn create_confirmed_subscriber(app: &TestApp) {
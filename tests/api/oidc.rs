@@ -0,0 +1,379 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wizard_blog_backend::configuration::OidcSettings;
+
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app, spawn_app_with};
+
+const CLIENT_ID: &str = "wizard-blog";
+const SIGNING_KEY: &str = include_str!("fixtures/oidc_signing_key.pem");
+/// The modulus of `SIGNING_KEY`, as published in the mock provider's JWKS.
+const SIGNING_KEY_MODULUS: &str = "68Oh7NYKm1XW3UAxmEV3A5WMI5IRz5zJTGShL1K0EIDZlwTaKaBerHCuE-7QvWieo_P19CLy5jOwhNkiieFm2qSWLz87nVNZx4CzDataUTTT_euI_tJOWYjEsJxCQbDYy2odofbU9nzj7h0i00Qkp2tRlj3sKPCQPnp1hP1NVB9xkIzymfVxo19ncJidF6JyPEwpK8GSRw-n_Xgb7Qgwseh1FVNEYShshWxdypeET9Yw9kfl3gp-EQ44LxBJPKi2SLuauHuSa5R7ZOBfSyOp9CV1WbcRmOljkotq2BB0f73aZ_Vg6TpMGs3fjBPXXCpTg9Teni5_-QpZTbbwJF2n6Q";
+
+/// A provider serving discovery and signing keys, whose token endpoint is
+/// mounted by each test.
+async fn spawn_idp() -> MockServer {
+    let idp = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/.well-known/openid-configuration"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "issuer": idp.uri(),
+            "authorization_endpoint": format!("{}/authorize", idp.uri()),
+            "token_endpoint": format!("{}/token", idp.uri()),
+            "jwks_uri": format!("{}/jwks", idp.uri()),
+        })))
+        .mount(&idp)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/jwks"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": "test-key",
+                "use": "sig",
+                "alg": "RS256",
+                "n": SIGNING_KEY_MODULUS,
+                "e": "AQAB",
+            }]
+        })))
+        .mount(&idp)
+        .await;
+    idp
+}
+
+fn oidc_settings(idp: &MockServer, link_by_email: bool) -> OidcSettings {
+    OidcSettings {
+        issuer_url: idp.uri(),
+        client_id: CLIENT_ID.into(),
+        client_secret: Secret::new("client-secret".into()),
+        display_name: "Company SSO".into(),
+        link_by_email,
+        trust_provider_mfa: false,
+        timeout_milliseconds: 2000,
+    }
+}
+
+async fn spawn_app_with_idp(idp: &MockServer, link_by_email: bool) -> TestApp {
+    spawn_app_with_oidc(oidc_settings(idp, link_by_email)).await
+}
+
+async fn spawn_app_with_oidc(settings: OidcSettings) -> TestApp {
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+    sqlx::query!(
+        "UPDATE users SET email = 'admin@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app
+}
+
+/// Mark the test user as enrolled, which is all the login flow looks at.
+async fn enable_two_factor(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET totp_secret = 'enrolled' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+struct AuthorizationRequest {
+    state: String,
+    nonce: String,
+    code_challenge: String,
+}
+
+async fn start_login(app: &TestApp, idp: &MockServer) -> AuthorizationRequest {
+    let response = app
+        .api_client
+        .get(format!("{}/login/oidc", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 303);
+    let location = reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+    assert!(
+        location
+            .as_str()
+            .starts_with(&format!("{}/authorize", idp.uri()))
+    );
+    let parameter = |name: &str| {
+        location
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .unwrap_or_else(|| panic!("The authorization request has no {name}"))
+    };
+    assert_eq!(parameter("client_id"), CLIENT_ID);
+    assert_eq!(parameter("response_type"), "code");
+    assert_eq!(parameter("code_challenge_method"), "S256");
+    assert_eq!(
+        parameter("redirect_uri"),
+        "http://127.0.0.1/login/oidc/callback"
+    );
+    AuthorizationRequest {
+        state: parameter("state"),
+        nonce: parameter("nonce"),
+        code_challenge: parameter("code_challenge"),
+    }
+}
+
+fn id_token(idp: &MockServer, audience: &str, nonce: &str, email_verified: bool) -> String {
+    let claims = serde_json::json!({
+        "iss": idp.uri(),
+        "aud": audience,
+        "sub": "idp-subject-42",
+        "exp": chrono::Utc::now().timestamp() + 300,
+        "iat": chrono::Utc::now().timestamp(),
+        "nonce": nonce,
+        "email": "Admin@Example.com",
+        "email_verified": email_verified,
+    });
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("test-key".into());
+    let key = EncodingKey::from_rsa_pem(SIGNING_KEY.as_bytes()).unwrap();
+    jsonwebtoken::encode(&header, &claims, &key).unwrap()
+}
+
+async fn mount_token_endpoint(idp: &MockServer, id_token: String) {
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "access_token": "access-token",
+            "token_type": "Bearer",
+            "id_token": id_token,
+        })))
+        .up_to_n_times(1)
+        .mount(idp)
+        .await;
+}
+
+async fn callback(app: &TestApp, state: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/login/oidc/callback", &app.address))
+        .query(&[("code", "authorization-code"), ("state", state)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn login_through_idp(app: &TestApp, idp: &MockServer) -> reqwest::Response {
+    let request = start_login(app, idp).await;
+    mount_token_endpoint(idp, id_token(idp, CLIENT_ID, &request.nonce, true)).await;
+    callback(app, &request.state).await
+}
+
+#[tokio::test]
+async fn the_login_page_offers_single_sign_on_when_configured() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, true).await;
+
+    let html_page = app.get_login_form().await;
+
+    assert!(html_page.contains(r#"<a href="/login/oidc">Log in with Company SSO</a>"#));
+}
+
+#[tokio::test]
+async fn single_sign_on_is_unavailable_unless_configured() {
+    let app = spawn_app().await;
+
+    assert!(!app.get_login_form().await.contains("/login/oidc"));
+    let response = app
+        .api_client
+        .get(format!("{}/login/oidc", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_verified_email_links_the_identity_and_logs_in() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, true).await;
+
+    let response = login_through_idp(&app, &idp).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("url=/admin/dashboard")
+    );
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}!", app.test_user.username)));
+    let linked = sqlx::query!("SELECT subject, user_id FROM oidc_identities")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(linked.subject, "idp-subject-42");
+    assert_eq!(linked.user_id, app.test_user.user_id);
+}
+
+#[tokio::test]
+async fn users_with_two_factor_still_enter_their_code_after_single_sign_on() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, true).await;
+    enable_two_factor(&app).await;
+
+    let response = login_through_idp(&app, &idp).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("url=/login/two-factor")
+    );
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    assert_eq!(app.get_login_two_factor().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_provider_can_be_trusted_to_enforce_the_second_factor() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_oidc(OidcSettings {
+        trust_provider_mfa: true,
+        ..oidc_settings(&idp, true)
+    })
+    .await;
+    enable_two_factor(&app).await;
+
+    let response = login_through_idp(&app, &idp).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_code_is_redeemed_with_the_pkce_verifier() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, true).await;
+    let request = start_login(&app, &idp).await;
+    mount_token_endpoint(&idp, id_token(&idp, CLIENT_ID, &request.nonce, true)).await;
+
+    callback(&app, &request.state).await;
+
+    let token_request = idp
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|r| r.url.path() == "/token")
+        .unwrap();
+    let form = String::from_utf8(token_request.body).unwrap();
+    let form = reqwest::Url::parse(&format!("http://form/?{form}")).unwrap();
+    let (_, verifier) = form
+        .query_pairs()
+        .find(|(key, _)| key == "code_verifier")
+        .unwrap();
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+    assert_eq!(challenge, request.code_challenge);
+}
+
+#[tokio::test]
+async fn linked_identities_log_in_by_subject() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, true).await;
+    login_through_idp(&app, &idp).await;
+    app.post_logout().await;
+    sqlx::query!("UPDATE users SET email = NULL")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = login_through_idp(&app, &idp).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn unverified_emails_are_not_linked() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, true).await;
+    let request = start_login(&app, &idp).await;
+    mount_token_endpoint(&idp, id_token(&idp, CLIENT_ID, &request.nonce, false)).await;
+
+    let response = callback(&app, &request.state).await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app.get_login_form().await.contains(
+        "<p><i>No active account is linked to this identity. Ask an owner to invite you.</i></p>"
+    ));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn emails_are_not_linked_unless_enabled() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, false).await;
+
+    let response = login_through_idp(&app, &idp).await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn a_mismatched_state_is_rejected() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, true).await;
+    let request = start_login(&app, &idp).await;
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&idp)
+        .await;
+
+    let response = callback(&app, &format!("{}x", request.state)).await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(
+        app.get_login_form()
+            .await
+            .contains("<p><i>Your login attempt expired, please log in again.</i></p>")
+    );
+}
+
+#[tokio::test]
+async fn id_tokens_with_the_wrong_nonce_are_rejected() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, true).await;
+    let request = start_login(&app, &idp).await;
+    mount_token_endpoint(&idp, id_token(&idp, CLIENT_ID, "another-nonce", true)).await;
+
+    let response = callback(&app, &request.state).await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(
+        app.get_login_form()
+            .await
+            .contains("<p><i>Single sign-on failed, please try again.</i></p>")
+    );
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn id_tokens_for_another_client_are_rejected() {
+    let idp = spawn_idp().await;
+    let app = spawn_app_with_idp(&idp, true).await;
+    let request = start_login(&app, &idp).await;
+    mount_token_endpoint(&idp, id_token(&idp, "another-client", &request.nonce, true)).await;
+
+    let response = callback(&app, &request.state).await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
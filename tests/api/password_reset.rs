@@ -0,0 +1,225 @@
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app};
+
+const EMAIL: &str = "owner@example.com";
+
+async fn spawn_app_with_user_email() -> TestApp {
+    let app = spawn_app().await;
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        EMAIL,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app
+}
+
+/// Request a reset for the test user and return the emailed link.
+async fn request_reset_link(app: &TestApp) -> reqwest::Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_forgot_password(EMAIL).await;
+    assert_is_redirect_to(&response, "/login/forgot");
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_links(email_request).html
+}
+
+fn token(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+fn reset_body(link: &reqwest::Url, password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token(link),
+        "new_password": password,
+        "new_password_check": password,
+    })
+}
+
+#[tokio::test]
+async fn unknown_emails_get_the_same_answer_but_no_email() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_forgot_password("nobody@example.com").await;
+    assert_is_redirect_to(&response, "/login/forgot");
+
+    let html_page = app.get_forgot_password_html().await;
+    assert!(html_page.contains(
+        "If an account with that email address exists, we have sent it a password reset link."
+    ));
+}
+
+#[tokio::test]
+async fn a_password_can_be_reset_through_the_emailed_link() {
+    let app = spawn_app_with_user_email().await;
+    let link = request_reset_link(&app).await;
+
+    let html_page = app
+        .api_client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"name="new_password""#));
+
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_reset_password(&reset_body(&link, &new_password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn reset_tokens_are_single_use() {
+    let app = spawn_app_with_user_email().await;
+    let link = request_reset_link(&app).await;
+
+    let response = app
+        .post_reset_password(&reset_body(&link, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_reset_password(&reset_body(&link, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login/forgot");
+
+    let html_page = app.get_forgot_password_html().await;
+    assert!(html_page.contains("This password reset link is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn a_failed_reset_leaves_the_link_usable() {
+    let app = spawn_app_with_user_email().await;
+    let link = request_reset_link(&app).await;
+    // Sabotage the database so that the new password cannot be saved
+    sqlx::query("ALTER TABLE users RENAME COLUMN password_hash TO password_hash_moved")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_reset_password(&reset_body(&link, &Uuid::new_v4().to_string()))
+        .await;
+    assert_eq!(response.status().as_u16(), 500);
+
+    sqlx::query("ALTER TABLE users RENAME COLUMN password_hash_moved TO password_hash")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app
+        .post_reset_password(&reset_body(&link, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn expired_reset_tokens_are_rejected() {
+    let app = spawn_app_with_user_email().await;
+    let link = request_reset_link(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let html_page = app
+        .api_client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("This password reset link is invalid or has expired."));
+    let response = app
+        .post_reset_password(&reset_body(&link, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login/forgot");
+}
+
+#[tokio::test]
+async fn reset_tokens_are_stored_hashed() {
+    let app = spawn_app_with_user_email().await;
+    let link = request_reset_link(&app).await;
+
+    let stored = sqlx::query!("SELECT token_hash FROM password_reset_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token(&link));
+}
+
+#[tokio::test]
+async fn resetting_a_password_logs_out_existing_sessions() {
+    let app = spawn_app_with_user_email().await;
+    app.login_as(&app.test_user).await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let link = request_reset_link(&app).await;
+    app.post_reset_password(&reset_body(&link, &Uuid::new_v4().to_string()))
+        .await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn new_passwords_must_match() {
+    let app = spawn_app_with_user_email().await;
+    let link = request_reset_link(&app).await;
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": token(&link),
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        &format!("/login/reset?token={}", token(&link))
+    );
+
+    // The token was not spent by the failed attempt
+    let response = app
+        .post_reset_password(&reset_body(&link, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
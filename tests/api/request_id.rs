@@ -0,0 +1,147 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::spawn_app;
+
+const SUBSCRIPTION_BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+#[tokio::test]
+async fn a_request_id_is_generated_when_none_is_supplied() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/health", &app.address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    let request_id = response.headers().get("X-Request-Id").unwrap();
+    assert!(Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn an_upstream_request_id_is_echoed() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/health", &app.address))
+        .header("X-Request-Id", "edge-1234")
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.headers().get("X-Request-Id").unwrap(), "edge-1234");
+}
+
+#[tokio::test]
+async fn an_unsafe_upstream_request_id_is_replaced() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/health", &app.address))
+        .header("X-Request-Id", "<script>alert(1)</script>")
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    let request_id = response.headers().get("X-Request-Id").unwrap();
+    assert!(Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn error_pages_show_the_request_id() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Request-Id", "edge-400")
+        .body("name=le%20guin&email=definitely-not-an-email")
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.headers().get("X-Request-Id").unwrap(), "edge-400");
+    assert!(response.text().await.unwrap().contains("edge-400"));
+}
+
+#[tokio::test]
+async fn subscriptions_record_the_request_that_created_them() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Request-Id", "edge-subscribe")
+        .body(SUBSCRIPTION_BODY)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    let saved = sqlx::query!("SELECT request_id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.request_id.as_deref(), Some("edge-subscribe"));
+}
+
+#[tokio::test]
+async fn emails_carry_the_request_id() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Request-Id", "edge-email")
+        .body(SUBSCRIPTION_BODY)
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["Headers"],
+        serde_json::json!([{ "Name": "X-Request-Id", "Value": "edge-email" }])
+    );
+}
+
+#[tokio::test]
+async fn audit_events_record_the_request_that_caused_them() {
+    let app = spawn_app().await;
+
+    app.api_client
+        .post(format!("{}/login", &app.address))
+        .header("X-Request-Id", "edge-login")
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong-password"
+        }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    let event = sqlx::query!("SELECT action, request_id FROM audit_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the audit event.");
+    assert_eq!(event.action, "login.failed");
+    assert_eq!(event.request_id.as_deref(), Some("edge-login"));
+}
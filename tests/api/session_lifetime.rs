@@ -0,0 +1,157 @@
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app, spawn_app_with};
+
+fn session_cookie(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.starts_with(&format!("{name}=")))
+        .map(str::to_owned)
+}
+
+async fn login_remembered(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+        "remember_me": "on",
+    }))
+    .await
+}
+
+async fn age_session(app: &TestApp, created_ago: &str, last_seen_ago: &str) {
+    sqlx::query(
+        r#"
+UPDATE user_sessions
+SET created_at = now() - $2::text::interval, last_seen_at = now() - $3::text::interval
+WHERE user_id = $1
+"#,
+    )
+    .bind(app.test_user.user_id)
+    .bind(created_ago)
+    .bind(last_seen_ago)
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_session_cookie_uses_the_configured_name() {
+    let app = spawn_app_with(|c| c.session.cookie_name = "wizard_session".into()).await;
+
+    let response = app.login_as(&app.test_user).await;
+
+    assert!(session_cookie(&response, "wizard_session").is_some());
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sessions_without_remember_me_end_with_the_browser() {
+    let app = spawn_app().await;
+
+    let response = app.login_as(&app.test_user).await;
+
+    let cookie = session_cookie(&response, "id").unwrap();
+    assert!(!cookie.contains("Max-Age"));
+}
+
+#[tokio::test]
+async fn remember_me_makes_the_session_cookie_persistent() {
+    let app = spawn_app_with(|c| c.session.remember_me_ttl_seconds = 600).await;
+
+    let response = login_remembered(&app).await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let cookie = session_cookie(&response, "id").unwrap();
+    assert!(cookie.contains("Max-Age=600"));
+}
+
+#[tokio::test]
+async fn remembered_session_cookies_are_refreshed_on_activity() {
+    let app = spawn_app_with(|c| c.session.remember_me_ttl_seconds = 600).await;
+    login_remembered(&app).await;
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let cookie = session_cookie(&response, "id").unwrap();
+    assert!(cookie.contains("Max-Age=600"));
+    // The refreshed cookie still identifies the session
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn remembered_session_cookies_are_not_refreshed_without_rolling() {
+    let app = spawn_app_with(|c| c.session.rolling = false).await;
+    login_remembered(&app).await;
+    // The first visit stores a CSRF token, changing the session state
+    app.get_admin_dashboard().await;
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(session_cookie(&response, "id").is_none());
+}
+
+#[tokio::test]
+async fn idle_sessions_are_logged_out() {
+    let app = spawn_app_with(|c| c.session.idle_timeout_seconds = 3600).await;
+    app.login_as(&app.test_user).await;
+
+    age_session(&app, "2 hours", "2 hours").await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn activity_keeps_rolling_sessions_alive() {
+    let app = spawn_app_with(|c| c.session.idle_timeout_seconds = 3600).await;
+    app.login_as(&app.test_user).await;
+
+    age_session(&app, "2 hours", "10 minutes").await;
+
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn without_rolling_the_idle_timeout_counts_from_login() {
+    let app = spawn_app_with(|c| {
+        c.session.idle_timeout_seconds = 3600;
+        c.session.rolling = false;
+    })
+    .await;
+    app.login_as(&app.test_user).await;
+
+    age_session(&app, "2 hours", "10 minutes").await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn remembered_sessions_outlive_the_idle_timeout() {
+    let app = spawn_app_with(|c| {
+        c.session.idle_timeout_seconds = 3600;
+        c.session.remember_me_ttl_seconds = 7 * 24 * 3600;
+    })
+    .await;
+    login_remembered(&app).await;
+
+    age_session(&app, "2 days", "2 days").await;
+
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sessions_end_after_their_absolute_lifetime() {
+    let app = spawn_app_with(|c| {
+        c.session.absolute_lifetime_seconds = 24 * 3600;
+        c.session.remember_me_ttl_seconds = 7 * 24 * 3600;
+    })
+    .await;
+    login_remembered(&app).await;
+
+    age_session(&app, "2 days", "1 minute").await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
@@ -0,0 +1,103 @@
+use crate::helpers::{TestApp, TestUser, assert_is_redirect_to, spawn_app, spawn_app_with};
+use secrecy::Secret;
+
+const BOOTSTRAP_TOKEN: &str = "test-bootstrap-token";
+
+async fn spawn_empty_app() -> TestApp {
+    let app =
+        spawn_app_with(|c| c.bootstrap_token = Some(Secret::new(BOOTSTRAP_TOKEN.into()))).await;
+    sqlx::query!("DELETE FROM users")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app
+}
+
+fn setup_body(token: &str, user: &TestUser) -> serde_json::Value {
+    serde_json::json!({
+        "bootstrap_token": token,
+        "username": &user.username,
+        "password": &user.password,
+        "password_check": &user.password,
+    })
+}
+
+#[tokio::test]
+async fn the_first_owner_can_be_created_through_the_setup_page() {
+    let app = spawn_empty_app().await;
+    let owner = TestUser::generate();
+
+    let response = app.get_setup().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_setup(&setup_body(BOOTSTRAP_TOKEN, &owner)).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.login_as(&owner).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("Your role: owner"));
+}
+
+#[tokio::test]
+async fn setup_is_unavailable_once_a_user_exists() {
+    let app = spawn_empty_app().await;
+    let owner = TestUser::generate();
+    app.post_setup(&setup_body(BOOTSTRAP_TOKEN, &owner)).await;
+
+    let response = app.get_setup().await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app
+        .post_setup(&setup_body(BOOTSTRAP_TOKEN, &TestUser::generate()))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn setup_is_unavailable_without_a_bootstrap_token() {
+    let app = spawn_app_with(|c| c.bootstrap_token = None).await;
+    sqlx::query!("DELETE FROM users")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.get_setup().await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app
+        .post_setup(&setup_body(BOOTSTRAP_TOKEN, &TestUser::generate()))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn setup_rejects_an_invalid_bootstrap_token() {
+    let app = spawn_empty_app().await;
+    let owner = TestUser::generate();
+
+    let response = app.post_setup(&setup_body("wrong-token", &owner)).await;
+    assert_is_redirect_to(&response, "/setup");
+    let html_page = app.get_setup().await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>Invalid bootstrap token.</i></p>"));
+
+    let response = app.login_as(&owner).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn setup_is_not_reachable_on_an_app_with_users() {
+    let app = spawn_app().await;
+
+    let response = app.get_setup().await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_seeded_admin_is_not_present() {
+    let app = spawn_app().await;
+
+    let seeded = sqlx::query!("SELECT user_id FROM users WHERE username = 'harshvseadmin'")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(seeded.is_none());
+}
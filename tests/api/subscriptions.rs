@@ -1,9 +1,12 @@
+use chrono::{DateTime, Utc};
 use wiremock::{
     Mock, ResponseTemplate,
     matchers::{method, path},
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
+use wizard_blog_backend::configuration::get_configuration;
+use wizard_blog_backend::routes::{CONSENT_TEXT, CONSENT_TEXT_VERSION, sign_form_timestamp};
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -118,3 +121,328 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn the_subscription_form_shows_the_versioned_consent_text() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let html_page = app.get_home_html().await;
+    // Assert
+    assert!(html_page.contains(CONSENT_TEXT));
+    assert!(html_page.contains(&format!(
+        r#"data-consent-text-version="{}""#,
+        CONSENT_TEXT_VERSION
+    )));
+}
+
+#[tokio::test]
+async fn subscribe_records_the_consent_of_the_new_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=Harsh%20Verma&email=harshvse%40gmail.com&source=footer_form";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions(body.into()).await;
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT ip_address, user_agent, source, consent_text_version, confirmed_at \
+        FROM subscription_consents",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved consent.");
+    assert_eq!(saved.ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(
+        saved.user_agent.as_deref(),
+        Some("wizard-blog-backend-tests")
+    );
+    assert_eq!(saved.source, "footer_form");
+    assert_eq!(saved.consent_text_version, CONSENT_TEXT_VERSION);
+    assert!(saved.confirmed_at.is_none());
+}
+
+#[tokio::test]
+async fn subscribe_ignores_forwarding_headers_when_recording_the_consent_ip() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .header("Forwarded", "for=203.0.113.7")
+        .body("name=Harsh%20Verma&email=harshvse%40gmail.com")
+        .send()
+        .await
+        .expect("failed to execute request");
+    // Assert
+    let saved = sqlx::query!("SELECT ip_address FROM subscription_consents")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved consent.");
+    assert_eq!(saved.ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn subscribe_records_the_forwarded_client_ip_behind_a_trusted_proxy() {
+    // Arrange
+    let app = spawn_app_with(|c| c.trusted_proxies = vec!["127.0.0.1".parse().unwrap()]).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header(
+            "Forwarded",
+            "for=198.51.100.4, for=\"[2001:db8::1]:4711\";proto=https",
+        )
+        .body("name=Harsh%20Verma&email=harshvse%40gmail.com")
+        .send()
+        .await
+        .expect("failed to execute request");
+    // Assert
+    let saved = sqlx::query!("SELECT ip_address FROM subscription_consents")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved consent.");
+    assert_eq!(saved.ip_address.as_deref(), Some("2001:db8::1"));
+}
+
+#[tokio::test]
+async fn subscribe_returns_429_when_the_same_email_is_used_too_often() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscriptions.max_attempts_per_email = 1).await;
+    let body = "name=Harsh%20Verma&email=harshvse%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions(body.into()).await;
+    let response = app.post_subscriptions(body.into()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0);
+}
+
+#[tokio::test]
+async fn subscribe_returns_429_when_the_same_ip_subscribes_too_often() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscriptions.max_attempts_per_ip = 2).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // Act
+    for i in 0..2 {
+        let response = app
+            .post_subscriptions(format!(
+                "name=Harsh%20Verma&email=harshvse{}%40gmail.com",
+                i
+            ))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app
+        .post_subscriptions("name=Harsh%20Verma&email=harshvse%40gmail.com".into())
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn subscribe_limits_each_client_behind_a_trusted_proxy_separately() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+        c.subscriptions.max_attempts_per_ip = 1;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let subscribe_from = |client: &'static str, i: usize| {
+        app.api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", format!("{}, 127.0.0.1", client))
+            .body(format!(
+                "name=Harsh%20Verma&email=harshvse{}%40gmail.com",
+                i
+            ))
+            .send()
+    };
+    // Act
+    let first = subscribe_from("203.0.113.7", 0).await.unwrap();
+    let second = subscribe_from("203.0.113.8", 1).await.unwrap();
+    let repeated = subscribe_from("203.0.113.7", 2).await.unwrap();
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    assert_eq!(repeated.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn subscribe_silently_drops_submissions_filling_the_honeypot() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=Harsh%20Verma&email=harshvse%40gmail.com&contact_me_by_fax_only=1";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn subscribe_rejects_submissions_without_a_form_token() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscriptions.min_form_fill_milliseconds = 500).await;
+    let body = "name=Harsh%20Verma&email=harshvse%40gmail.com";
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribe_drops_forms_submitted_faster_than_the_minimum_fill_time() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscriptions.min_form_fill_milliseconds = 60_000).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let form_token = form_token(&app.get_home_html().await);
+    // Act
+    let response = app
+        .post_subscriptions(format!(
+            "name=Harsh%20Verma&email=harshvse%40gmail.com&form_token={}",
+            form_token
+        ))
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribe_accepts_forms_submitted_after_the_minimum_fill_time() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscriptions.min_form_fill_milliseconds = 500).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let form_token = form_token(&app.get_home_html().await);
+    tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+    // Act
+    let response = app
+        .post_subscriptions(format!(
+            "name=Harsh%20Verma&email=harshvse%40gmail.com&form_token={}",
+            form_token
+        ))
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribe_rejects_expired_form_tokens() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.subscriptions.min_form_fill_milliseconds = 500;
+        c.subscriptions.max_form_age_seconds = 3600;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let form_token = signed_form_token(Utc::now() - chrono::Duration::hours(2));
+    // Act
+    let response = app
+        .post_subscriptions(format!(
+            "name=Harsh%20Verma&email=harshvse%40gmail.com&form_token={}",
+            form_token
+        ))
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribe_rejects_form_tokens_from_the_future() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscriptions.min_form_fill_milliseconds = 500).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let form_token = signed_form_token(Utc::now() + chrono::Duration::hours(1));
+    // Act
+    let response = app
+        .post_subscriptions(format!(
+            "name=Harsh%20Verma&email=harshvse%40gmail.com&form_token={}",
+            form_token
+        ))
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+/// A form token signed with the application's key, as if the form had been
+/// rendered at `rendered_at`.
+fn signed_form_token(rendered_at: DateTime<Utc>) -> String {
+    let configuration = get_configuration().expect("Failed to get configuration");
+    sign_form_timestamp(&configuration.hmac_secret, rendered_at)
+}
+
+/// Extract the signed `form_token` embedded in the subscription form.
+fn form_token(html_page: &str) -> String {
+    let marker = r#"name="form_token" value=""#;
+    let start = html_page.find(marker).expect("form token is missing") + marker.len();
+    let end = start + html_page[start..].find('"').unwrap();
+    html_page[start..end].to_string()
+}
@@ -50,7 +50,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     app.post_subscriptions(body.into()).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
 
-    let confirmation_link = app.get_confirmation_links(&email_request);
+    let confirmation_link = app.get_confirmation_links(email_request);
 
     reqwest::get(confirmation_link.html)
         .await
@@ -67,3 +67,35 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "Harsh Verma");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_records_the_confirmation_time() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=Harsh%20Verma&email=harshvse%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request);
+
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT consented_at, confirmed_at FROM subscription_consents",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved consent");
+    let confirmed_at = saved
+        .confirmed_at
+        .expect("confirmation time was not recorded");
+    assert!(confirmed_at >= saved.consented_at);
+}
@@ -0,0 +1,51 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::spawn_app;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+#[tokio::test]
+async fn an_incoming_traceparent_is_propagated_to_the_email_provider() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("traceparent", format!("00-{TRACE_ID}-00f067aa0ba902b7-01"))
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request.headers["traceparent"].to_str().unwrap();
+    let parts: Vec<&str> = traceparent.split('-').collect();
+    assert_eq!(parts[1], TRACE_ID);
+    // The provider call is a new span within the same trace
+    assert_ne!(parts[2], "00f067aa0ba902b7");
+}
+
+#[tokio::test]
+async fn emails_sent_outside_a_propagated_trace_start_their_own() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request.headers["traceparent"].to_str().unwrap();
+    assert!(!traceparent.contains(TRACE_ID));
+}
@@ -0,0 +1,166 @@
+use crate::helpers::{TestApp, assert_is_redirect_to, extract_between, spawn_app, spawn_app_with};
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::Builder;
+
+/// The code an authenticator app would show `offset` seconds from now.
+fn totp_code(secret: &str, offset: u64) -> String {
+    let totp = Builder::new()
+        .with_secret(totp_rs::Secret::try_from_base32(secret).unwrap())
+        .build()
+        .unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    totp.generate(now + offset).to_string()
+}
+
+async fn login(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await
+}
+
+/// Enroll the test user, returning the TOTP secret and the recovery codes.
+async fn enroll(app: &TestApp) -> (String, Vec<String>) {
+    let html_page = app.get_admin_two_factor_html().await;
+    let secret = extract_between(&html_page, "<code>", "</code>");
+    let response = app
+        .post_admin_two_factor("/admin/two-factor", &totp_code(&secret, 0))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Two-factor authentication is now enabled."));
+    let recovery_codes = html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|item| item[..item.find("</code>").unwrap()].to_string())
+        .collect();
+    (secret, recovery_codes)
+}
+
+#[tokio::test]
+async fn users_can_enroll_and_then_login_with_a_totp_code() {
+    let app = spawn_app().await;
+    login(&app).await;
+    let (secret, recovery_codes) = enroll(&app).await;
+    assert_eq!(recovery_codes.len(), 10);
+    app.post_logout().await;
+
+    // The password alone is no longer enough
+    let response = login(&app).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // The enrollment code's time step is spent, so use the next one
+    let response = app.post_login_two_factor(&totp_code(&secret, 30)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}!", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn an_invalid_code_is_rejected_with_a_flash_message() {
+    let app = spawn_app().await;
+    login(&app).await;
+    enroll(&app).await;
+    app.post_logout().await;
+
+    login(&app).await;
+    let response = app.post_login_two_factor("000000").await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+
+    let html_page = app.get_login_two_factor().await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>Invalid authentication code.</i></p>"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_totp_code_cannot_be_replayed() {
+    let app = spawn_app().await;
+    login(&app).await;
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+
+    let code = totp_code(&secret, 30);
+    login(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    login(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn recovery_codes_can_only_be_used_once() {
+    let app = spawn_app().await;
+    login(&app).await;
+    let (_, recovery_codes) = enroll(&app).await;
+    app.post_logout().await;
+
+    login(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("You have 9 unused recovery codes left."));
+    app.post_logout().await;
+
+    login(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn the_second_step_requires_a_pending_login() {
+    let app = spawn_app().await;
+
+    let response = app.get_login_two_factor().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.post_login_two_factor("123456").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn users_without_two_factor_must_enroll_when_it_is_required() {
+    let app = spawn_app_with(|c| c.two_factor.required = true).await;
+
+    let response = login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+
+    enroll(&app).await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn two_factor_can_be_disabled_with_a_valid_code() {
+    let app = spawn_app().await;
+    login(&app).await;
+    let (_, recovery_codes) = enroll(&app).await;
+
+    let response = app
+        .post_admin_two_factor("/admin/two-factor/disable", "wrong")
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let html_page = app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("<p><i>The authentication code is incorrect.</i></p>"));
+
+    let response = app
+        .post_admin_two_factor("/admin/two-factor/disable", &recovery_codes[0])
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let html_page = app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("<p><i>Two-factor authentication has been disabled.</i></p>"));
+    app.post_logout().await;
+
+    let response = login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}